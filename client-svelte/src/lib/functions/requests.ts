@@ -25,6 +25,24 @@ export async function postJoinGame(game_name: string, name: string) {
     return request;
 }
 
+export async function postReconnect(game_name: string, name: string, token: string) {
+    const response: Response = await fetch(getBaseServerPath() + game_name + "/reconnect", {
+        method: "POST",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({
+            player: name,
+            token: token
+        })
+    })
+    return response;
+}
+
+// The server shows the player as connected for as long as this stays open
+export function listenToGame(game_name: string, name: string, token: string) {
+    const params = new URLSearchParams({ player: name, token: token });
+    return new EventSource(getBaseServerPath() + game_name + "/listen?" + params);
+}
+
 export async function getGame(game_name: string) {
     const response: Response = await fetch(getBaseServerPath() + game_name, {
         method: "GET",
@@ -34,7 +52,7 @@ export async function getGame(game_name: string) {
 }
 
 
-export async function postWager(game_name: string, name: string, guess: number | null, wager: number) {
+export async function postWager(game_name: string, name: string, guess: number | null, wager: number, all_in: boolean = false) {
     const response: Response = await fetch(getBaseServerPath() + game_name + "/wager", {
         method: "POST",
         headers: { "Content-Type": "application/json" },
@@ -42,23 +60,23 @@ export async function postWager(game_name: string, name: string, guess: number |
             player: name,
             guess: guess,
             wager: wager,
+            all_in: all_in,
         })
     })
     return response;
 }
 
 
-// export async function deletePlayerFromGame(game_name: string, name: string) {
-//     const response: Response = await fetch(getBaseServerPath() + game_name + "/exit", {
-//         method: "DELETE",
-//         mode: "no-cors",
-//         headers: {"Content-Type": "application/json"},
-//         body: JSON.stringify({
-//             player: name,
-//         })
-//     })
-//     return response;
-// }
+export async function deletePlayerFromGame(game_name: string, name: string) {
+    const response: Response = await fetch(getBaseServerPath() + game_name + "/exit", {
+        method: "DELETE",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({
+            player: name,
+        })
+    })
+    return response;
+}
 
 
 export async function postGuess(game_name: string, name: string, guess: number) {
@@ -0,0 +1,83 @@
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    strategy::RoundView,
+    types::{AnswerAmount, Player, ScoreAmount},
+};
+
+/// How well a bot plays
+#[derive(Default, Clone, Copy, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub(crate) enum Difficulty {
+    /// Guesses without looking at the answer and wagers at random
+    Easy,
+    /// Guesses within half the answer either way and wagers on the board's middle guess
+    #[default]
+    Medium,
+    /// Guesses close to the answer and wagers on the guess nearest its own estimate
+    Hard,
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct BotData {
+    /// The name the bot plays under
+    pub player: Player,
+    /// How well the bot plays
+    #[serde(default)]
+    pub difficulty: Difficulty,
+}
+
+/// The highest guess an easy bot will make
+const EASY_GUESS_LIMIT: AnswerAmount = 1000;
+
+impl Difficulty {
+    /// Come up with a guess for the round, the answer is only used to decide how close to get
+    pub(crate) fn guess(
+        &self,
+        view: &RoundView,
+        answer: AnswerAmount,
+        rng: &mut dyn RngCore,
+    ) -> AnswerAmount {
+        let spread = match self {
+            Difficulty::Easy => {
+                // Without the answer the best hint is what everyone else thinks
+                let mut guesses: Vec<AnswerAmount> = view.guesses.iter().map(|g| g.guess).collect();
+                guesses.sort_unstable();
+                return match guesses.get(guesses.len() / 2) {
+                    Some(median) => rng.gen_range(median / 2..=median.saturating_mul(2)),
+                    None => rng.gen_range(1..=EASY_GUESS_LIMIT),
+                };
+            }
+            Difficulty::Medium => 0.5,
+            Difficulty::Hard => 0.15,
+        };
+        let noise = rng.gen_range(-spread..=spread);
+        (f64::from(answer) * (1.0 + noise)).round() as AnswerAmount
+    }
+
+    /// Pick a guess on the board to back, or none if the answer is below every guess, and how
+    /// much of the bankroll to put on it
+    pub(crate) fn wager(
+        &self,
+        view: &RoundView,
+        rng: &mut dyn RngCore,
+    ) -> (Option<AnswerAmount>, ScoreAmount) {
+        let board = view.board();
+        let bankroll = view.bankroll.max(0);
+        match self {
+            Difficulty::Easy => {
+                let target = (!board.is_empty()).then(|| board[rng.gen_range(0..board.len())]);
+                (target, rng.gen_range(0..=bankroll))
+            }
+            Difficulty::Medium => (board.get(board.len() / 2).copied(), bankroll / 2),
+            Difficulty::Hard => {
+                // Its own guess is its best estimate, so back the highest guess not above it
+                let estimate = view.own_guess.unwrap_or(AnswerAmount::MAX);
+                let target = board.iter().rev().find(|g| **g <= estimate).copied();
+                // Widened since a bankroll can be as large as a score gets
+                let amount = i64::from(bankroll) * 3 / 4;
+                (target, amount as ScoreAmount)
+            }
+        }
+    }
+}
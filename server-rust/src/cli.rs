@@ -0,0 +1,289 @@
+use rocket::config::LogLevel;
+use std::{net::IpAddr, path::PathBuf};
+use structopt::StructOpt;
+
+use crate::{
+    config::{AppConfig, ServerConfig},
+    export::results_csv,
+    question_lookup::{parse_question, read_lines, QuestionLookup},
+    simulator::{simulate, Simulation},
+    store::Store,
+    strategy::strategy_named,
+    types::{Game, GameId, ScoreAmount, ScoringRules},
+};
+
+#[derive(Debug, StructOpt)]
+pub(crate) struct Opt {
+    /// A TOML file to read settings from, flags and environment variables take precedence.
+    #[structopt(long = "config", global = true)]
+    pub config: Option<PathBuf>,
+    /// The path to a file containing newline delimited questions.
+    #[structopt(long = "questions-file", global = true)]
+    pub questions_file: Option<PathBuf>,
+    /// The directory profiles, finished games and imported questions are kept in, they are lost
+    /// on restart if not given.
+    #[structopt(long = "data-dir", global = true)]
+    pub data_dir: Option<PathBuf>,
+    // Server flags given before any subcommand, so `--port 8000` on its own still serves
+    #[structopt(flatten)]
+    pub serve: ServeOpt,
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+pub(crate) enum Command {
+    /// Run the game server, the default when no subcommand is given.
+    Serve(ServeOpt),
+    /// Check every line of a questions file can be read.
+    ValidateQuestions {
+        /// The questions file to check.
+        file: PathBuf,
+    },
+    /// Add the questions in a file to the question bank in the data directory.
+    ImportQuestions {
+        /// The questions file to import.
+        file: PathBuf,
+    },
+    /// Print a finished game from the data directory.
+    ExportGame {
+        /// The id of the game, the most recently finished is used if it was played more than once.
+        game_id: GameId,
+        /// Print the results of each round as CSV instead of the full game as JSON.
+        #[structopt(long = "csv")]
+        csv: bool,
+    },
+    /// List the finished games in the data directory.
+    ListGames,
+    /// Play games between synthetic players in-process and print how they went.
+    Simulate(SimulateOpt),
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Command::Serve(ServeOpt::default())
+    }
+}
+
+#[derive(Debug, Default, StructOpt)]
+pub(crate) struct ServeOpt {
+    /// An IP address the application will listen on [default: 0.0.0.0].
+    #[structopt(long = "host", short = "H")]
+    address: Option<IpAddr>,
+    /// A port number to listen on [default: 8172].
+    #[structopt(long = "port", short = "P")]
+    port: Option<u16>,
+    /// The ranking points given for each final placing in a season game, first place first
+    /// [default: 10,6,3].
+    #[structopt(long = "season-points", use_delimiter = true)]
+    season_points: Option<Vec<u32>>,
+    /// The number of seconds a game can go without any player activity before it is deleted, 0
+    /// keeps idle games forever [default: 21600].
+    #[structopt(long = "game-ttl")]
+    game_ttl: Option<u64>,
+    /// The log level [default: normal].
+    #[structopt(
+        long = "log-level",
+        possible_values = &["off", "debug", "normal", "critical"]
+    )]
+    log_level: Option<LogLevel>,
+}
+
+impl ServeOpt {
+    /// Override the server settings with any flags that were given
+    pub(crate) fn apply(self, server: &mut ServerConfig) {
+        server.address = self.address.unwrap_or(server.address);
+        server.port = self.port.unwrap_or(server.port);
+        server.log_level = self.log_level.unwrap_or(server.log_level);
+        server.game_ttl = self.game_ttl.unwrap_or(server.game_ttl);
+        if let Some(season_points) = self.season_points {
+            server.season_points = season_points;
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub(crate) struct SimulateOpt {
+    /// The number of games to play.
+    #[structopt(long = "games", default_value = "1000")]
+    games: usize,
+    /// The most rounds a game lasts.
+    #[structopt(long = "max-rounds", default_value = "10")]
+    max_rounds: usize,
+    /// End a game early once someone reaches this score.
+    #[structopt(long = "target-score")]
+    target_score: Option<ScoreAmount>,
+    /// The strategy of each synthetic player, one seat each.
+    #[structopt(
+        long = "players",
+        default_value = "easy,medium,hard,scoreaware",
+        use_delimiter = true
+    )]
+    players: Vec<String>,
+    /// How many times the wager a correct wager pays out.
+    #[structopt(long = "payout-ratio", default_value = "3")]
+    payout_ratio: ScoreAmount,
+    /// The extra points for having the closest guess.
+    #[structopt(long = "closest-guess-bonus", default_value = "3")]
+    closest_guess_bonus: ScoreAmount,
+    /// The score everyone starts the game with.
+    #[structopt(long = "starting-score", default_value = "1")]
+    starting_score: ScoreAmount,
+    /// The smallest wager allowed, a player with less can still go all in.
+    #[structopt(long = "minimum-wager", default_value = "0")]
+    minimum_wager: ScoreAmount,
+    /// The score a player who has gone broke is topped back up to after a round, 0 leaves them
+    /// broke.
+    #[structopt(long = "reseed-score", default_value = "1")]
+    reseed_score: ScoreAmount,
+}
+
+/// Print a message and stop with a failing exit code
+fn fail(message: String) -> ! {
+    eprintln!("{message}");
+    std::process::exit(1);
+}
+
+/// Read the config file and environment, with the shared flags taking precedence
+pub(crate) fn load_config(opt: &Opt) -> AppConfig {
+    let mut config = AppConfig::load(opt.config.as_deref())
+        .unwrap_or_else(|e| fail(format!("Failed to load config, err: {e}")));
+    if let Some(questions_file) = &opt.questions_file {
+        config.questions.file = Some(questions_file.clone());
+    }
+    if let Some(data_dir) = &opt.data_dir {
+        config.storage.data_dir = Some(data_dir.clone());
+    }
+    if let Err(e) = config.game.scoring.validate() {
+        fail(format!("Invalid game settings in config, err: {e}"));
+    }
+    config
+}
+
+/// Open the data directory, or an empty store if none was given
+pub(crate) fn open_store(data_dir: Option<&PathBuf>) -> Store {
+    match data_dir {
+        Some(data_dir) => Store::open(data_dir).unwrap_or_else(|e| {
+            fail(format!(
+                "Failed to open data directory {data_dir:?}, err: {e}"
+            ))
+        }),
+        None => Store::default(),
+    }
+}
+
+/// Load the questions file along with the question bank in the data directory
+pub(crate) fn load_questions(questions_file: Option<&PathBuf>, store: &Store) -> QuestionLookup {
+    let mut questions = QuestionLookup::default();
+    for questions_file in questions_file
+        .into_iter()
+        .cloned()
+        .chain(store.questions_file())
+    {
+        if let Err(e) = questions.populate_from_file(&questions_file) {
+            fail(format!(
+                "Failed to populate questions from file {questions_file:?}, err: {e}"
+            ));
+        }
+    }
+    questions
+}
+
+pub(crate) fn validate_questions(file: &PathBuf) {
+    let lines =
+        read_lines(file).unwrap_or_else(|e| fail(format!("Failed to read {file:?}, err: {e}")));
+    let mut invalid = 0;
+    for (index, line) in lines.iter().enumerate() {
+        if let Err(e) = parse_question(line) {
+            println!("line {}: {e}", index + 1);
+            invalid += 1;
+        }
+    }
+    println!("{} questions, {invalid} invalid", lines.len());
+    if invalid > 0 {
+        std::process::exit(1);
+    }
+}
+
+pub(crate) fn import_questions(file: &PathBuf, store: &Store, data_dir: Option<&PathBuf>) {
+    if data_dir.is_none() {
+        fail(String::from("Importing questions needs a --data-dir"));
+    }
+    let lines =
+        read_lines(file).unwrap_or_else(|e| fail(format!("Failed to read {file:?}, err: {e}")));
+    let mut questions = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        match parse_question(line) {
+            Ok(question) => questions.push(question),
+            Err(e) => fail(format!("Nothing imported, line {}: {e}", index + 1)),
+        }
+    }
+    let total = questions.len();
+    match store.import_questions(questions) {
+        Ok(added) => println!(
+            "Imported {added} of {total} questions, the rest were already in the question bank"
+        ),
+        Err(e) => fail(format!("Failed to import questions, err: {e}")),
+    }
+}
+
+pub(crate) fn export_game(game_id: &str, csv: bool, store: &Store) {
+    let output = store.games(|games| {
+        let export = games
+            .iter()
+            .rev()
+            .find(|game| game.game_id == game_id)
+            .unwrap_or_else(|| fail(format!("No finished game {game_id}")));
+        if csv {
+            results_csv(&Game::replay(export.events.clone()))
+        } else {
+            serde_json::to_string_pretty(export).expect("to export serialize")
+        }
+    });
+    println!("{}", output.trim_end());
+}
+
+pub(crate) fn list_games(store: &Store) {
+    store.games(|games| {
+        println!(
+            "{:<16} {:<12} {:>7} {:>6} {:>12}",
+            "game", "season", "players", "rounds", "finished at"
+        );
+        for game in games {
+            println!(
+                "{:<16} {:<12} {:>7} {:>6} {:>12}",
+                game.game_id,
+                game.settings.season.as_deref().unwrap_or("-"),
+                game.players.len(),
+                game.rounds.len(),
+                game.events.last().map_or(0, |recorded| recorded.at),
+            );
+        }
+    });
+}
+
+pub(crate) fn run_simulation(opt: SimulateOpt, questions: &QuestionLookup) {
+    let mut strategies = Vec::new();
+    for name in &opt.players {
+        match strategy_named(name) {
+            Some(strategy) => strategies.push(strategy),
+            None => fail(format!("Unknown strategy {name}")),
+        }
+    }
+    let simulation = Simulation {
+        games: opt.games,
+        max_rounds: opt.max_rounds,
+        target_score: opt.target_score,
+        scoring: ScoringRules {
+            payout_ratio: opt.payout_ratio,
+            closest_guess_bonus: opt.closest_guess_bonus,
+            starting_score: opt.starting_score,
+            minimum_wager: opt.minimum_wager,
+            reseed_score: opt.reseed_score,
+        },
+    };
+    if let Err(e) = simulation.scoring.validate() {
+        fail(format!("Invalid scoring rules, err: {e}"));
+    }
+    print!("{}", simulate(&strategies, questions, &simulation));
+}
@@ -0,0 +1,236 @@
+use displaydoc::Display;
+use rocket::config::LogLevel;
+use rocket_cors::{AllowedOrigins, Cors, CorsOptions, Method};
+use serde::{Deserialize, Deserializer};
+use std::{
+    fs, io,
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use thiserror::Error;
+use toml::{Table, Value};
+
+use crate::types::{GameSettings, GetQuestionLocation};
+
+/// Environment variables starting with this override the config file. Sections and keys are
+/// separated by a double underscore, for example `BRAINS_AND_BETS_SERVER__PORT=8080`.
+const ENV_PREFIX: &str = "BRAINS_AND_BETS_";
+
+/// Everything the server can be configured with. Values come from the config file, then
+/// environment variables, then command line flags, each overriding the last.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct AppConfig {
+    pub server: ServerConfig,
+    pub questions: QuestionsConfig,
+    /// The rules new games are played with unless their host picks others
+    pub game: GameSettings,
+    pub storage: StorageConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ServerConfig {
+    /// An IP address the application will listen on
+    pub address: IpAddr,
+    /// A port number to listen on
+    pub port: u16,
+    /// The log level
+    pub log_level: LogLevel,
+    /// The number of seconds a game can go without any player activity before it is deleted, 0
+    /// keeps idle games forever
+    pub game_ttl: u64,
+    /// The ranking points given for each final placing in a season game, first place first
+    pub season_points: Vec<u32>,
+    /// Which web pages may call the API
+    pub cors: CorsConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 8172,
+            log_level: LogLevel::Normal,
+            game_ttl: 21600,
+            season_points: vec![10, 6, 3],
+            cors: CorsConfig::default(),
+        }
+    }
+}
+
+/// The origin that allows any page to call the API
+const ANY_ORIGIN: &str = "*";
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CorsConfig {
+    /// The origins allowed to make cross-origin requests, `*` allows any origin
+    pub allowed_origins: Vec<String>,
+    /// The HTTP methods cross-origin requests may use
+    pub allowed_methods: Vec<String>,
+    /// If browsers may send cookies along with cross-origin requests, only allowed when the
+    /// origins are listed
+    pub allow_credentials: bool,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec![String::from(ANY_ORIGIN)],
+            allowed_methods: ["GET", "POST", "PATCH", "PUT", "DELETE"]
+                .map(String::from)
+                .to_vec(),
+            allow_credentials: false,
+        }
+    }
+}
+
+impl CorsConfig {
+    /// Build the fairing that answers preflight requests and adds the CORS headers
+    pub(crate) fn to_cors(&self) -> Result<Cors, ConfigError> {
+        let any_origin = self
+            .allowed_origins
+            .iter()
+            .any(|origin| origin == ANY_ORIGIN);
+        if any_origin && self.allow_credentials {
+            return Err(ConfigError::CredentialsWithAnyOrigin);
+        }
+        let allowed_origins = if any_origin {
+            AllowedOrigins::all()
+        } else {
+            AllowedOrigins::some_exact(&self.allowed_origins)
+        };
+        let allowed_methods = self
+            .allowed_methods
+            .iter()
+            .map(|method| {
+                Method::from_str(&method.to_uppercase())
+                    .map_err(|()| ConfigError::UnknownMethod(method.clone()))
+            })
+            .collect::<Result<_, _>>()?;
+        CorsOptions::default()
+            .allowed_origins(allowed_origins)
+            .allowed_methods(allowed_methods)
+            .allow_credentials(self.allow_credentials)
+            .to_cors()
+            .map_err(ConfigError::Cors)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct QuestionsConfig {
+    /// The path to a file containing newline delimited questions
+    #[serde(deserialize_with = "optional_string")]
+    pub file: Option<PathBuf>,
+    /// Where new games get questions from when their host does not say
+    pub source: GetQuestionLocation,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct StorageConfig {
+    /// The directory profiles, finished games and imported questions are kept in
+    #[serde(deserialize_with = "optional_string")]
+    pub data_dir: Option<PathBuf>,
+}
+
+/// Any single value a string setting could have been read as
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Scalar {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+/// Read an optional string setting as written even when it looks like a number or a boolean,
+/// since environment variables are read as TOML and `BRAINS_AND_BETS_GAME__SEASON=2024` would
+/// otherwise be an integer
+pub(crate) fn optional_string<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: From<String>,
+{
+    let value = Option::<Scalar>::deserialize(deserializer)?.map(|value| match value {
+        Scalar::String(value) => value,
+        Scalar::Integer(value) => value.to_string(),
+        Scalar::Float(value) => value.to_string(),
+        Scalar::Boolean(value) => value.to_string(),
+    });
+    Ok(value.map(T::from))
+}
+
+#[derive(Debug, Display, Error)]
+pub(crate) enum ConfigError {
+    /// failed to read config file {0:?}: {1}
+    Read(PathBuf, #[source] io::Error),
+    /// invalid config file {0:?}: {1}
+    Parse(PathBuf, #[source] toml::de::Error),
+    /// invalid config: {0}
+    Invalid(#[source] toml::de::Error),
+    /// unknown HTTP method {0:?} in the allowed CORS methods
+    UnknownMethod(String),
+    /// CORS credentials cannot be allowed for every origin, list the allowed origins instead
+    CredentialsWithAnyOrigin,
+    /// invalid CORS settings: {0}
+    Cors(#[source] rocket_cors::Error),
+}
+
+impl AppConfig {
+    /// Read the config file, if there is one, and apply any environment variable overrides
+    pub(crate) fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let mut table = match path {
+            Some(path) => {
+                let contents =
+                    fs::read_to_string(path).map_err(|e| ConfigError::Read(path.into(), e))?;
+                contents
+                    .parse::<Table>()
+                    .map_err(|e| ConfigError::Parse(path.into(), e))?
+            }
+            None => Table::new(),
+        };
+        apply_overrides(&mut table, std::env::vars());
+        let config: Self = Value::Table(table)
+            .try_into()
+            .map_err(ConfigError::Invalid)?;
+        // Catch bad CORS settings now rather than when the server starts
+        config.server.cors.to_cors()?;
+        Ok(config)
+    }
+}
+
+/// Set the value of every variable with the prefix in the table, creating sections as needed
+pub(crate) fn apply_overrides(table: &mut Table, vars: impl IntoIterator<Item = (String, String)>) {
+    for (name, value) in vars {
+        let Some(path) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let keys: Vec<String> = path.split("__").map(str::to_lowercase).collect();
+        let (key, sections) = keys.split_last().expect("split to give at least one key");
+        let mut table = &mut *table;
+        for section in sections {
+            let entry = table
+                .entry(section.clone())
+                .or_insert_with(|| Value::Table(Table::new()));
+            if !entry.is_table() {
+                *entry = Value::Table(Table::new());
+            }
+            table = entry.as_table_mut().expect("entry to be a table");
+        }
+        table.insert(key.clone(), parse_value(&value));
+    }
+}
+
+/// Read a variable as a TOML value so numbers, booleans and lists keep their type, falling back
+/// to a plain string
+fn parse_value(value: &str) -> Value {
+    format!("value = {value}")
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(value.to_string()))
+}
@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::types::{
+    AnswerAmount, Error, Game, GameEvent, GameId, GameSettings, GetQuestionLocation, Guess,
+    Guesses, Player, ProfileId, Question, RecordedEvent, Result, RoundState, ScoreAmount, Scores,
+    TeamName, Wager, Wagers,
+};
+
+/// Bumped whenever the layout of `GameExport` changes in a way older readers would misread
+pub(crate) const EXPORT_VERSION: u32 = 1;
+
+/// A complete, self-contained record of a game for archiving and analysis
+#[derive(Deserialize, Serialize)]
+pub(crate) struct GameExport {
+    /// The version of the export format
+    pub version: u32,
+    /// The id the game was played under
+    pub game_id: GameId,
+    /// The rules the game was played with
+    pub settings: GameSettings,
+    /// The location questions were taken from
+    pub question_location: GetQuestionLocation,
+    /// Everyone who took a seat in the game, including those who have since left
+    pub players: BTreeSet<Player>,
+    /// The persistent profiles players linked their seat to
+    #[serde(default)]
+    pub profiles: HashMap<Player, ProfileId>,
+    /// Every round in the order they were played
+    pub rounds: Vec<RoundExport>,
+    /// The final score of every player
+    pub scores: Scores,
+    /// Every action taken in the game, the game is rebuilt from these on import
+    pub events: Vec<RecordedEvent>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct RoundExport {
+    /// The question for the round, including its answer
+    pub question: Question,
+    /// The phase the round was in when the game was exported
+    pub phase: RoundState,
+    /// The guesses given in the round
+    pub guesses: Guesses,
+    /// The wagers made in the round
+    pub wagers: Wagers,
+    /// How each score changed in the round, empty if the round is not complete
+    pub score_changes: Scores,
+}
+
+impl GameExport {
+    pub(crate) fn new(game_id: GameId, game: &Game) -> Self {
+        let players = game
+            .events
+            .iter()
+            .filter_map(|recorded| match &recorded.event {
+                GameEvent::PlayerJoined { player, .. } => Some(player.clone()),
+                _ => None,
+            })
+            .chain(game.players.iter().cloned())
+            .chain(game.pending_players.iter().cloned())
+            .collect();
+        let rounds = game
+            .rounds
+            .iter()
+            .map(|round| RoundExport {
+                question: round.question.clone(),
+                phase: round.phase,
+                guesses: round.guesses.clone(),
+                wagers: round.wagers.clone(),
+                score_changes: if round.phase == RoundState::Complete {
+                    game.settings.scoring.score_changes(round)
+                } else {
+                    Scores::new()
+                },
+            })
+            .collect();
+        Self {
+            version: EXPORT_VERSION,
+            game_id,
+            settings: game.settings.clone(),
+            question_location: game.question_location,
+            players,
+            profiles: game.profiles.clone(),
+            rounds,
+            scores: game.get_score(),
+            events: game.events.clone(),
+        }
+    }
+
+    /// Rebuild the game from its events, the rest of the export is only there for readers. The
+    /// events are checked since the export may have been edited.
+    pub(crate) fn into_game(self) -> Result<Game> {
+        if self.version != EXPORT_VERSION {
+            return Err(Error::UnsupportedExportVersion);
+        }
+        Game::replay_checked(self.events)
+    }
+}
+
+/// One line of the results spreadsheet, describing what a player did in a round
+#[derive(Serialize)]
+struct ResultRow<'a> {
+    round: usize,
+    player: &'a str,
+    team: Option<&'a str>,
+    question: &'a str,
+    answer: AnswerAmount,
+    guess: Option<AnswerAmount>,
+    /// The guess wagered on, empty for a wager that the answer is below every guess
+    wager_target: Option<AnswerAmount>,
+    wager_amount: Option<ScoreAmount>,
+    score_change: ScoreAmount,
+}
+
+/// The results of every complete round as CSV, one row per player per round
+pub(crate) fn results_csv(game: &Game) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let rosters = team_rosters(&game.events);
+    let no_teams = HashMap::new();
+    let complete_rounds = game
+        .rounds
+        .iter()
+        .enumerate()
+        .filter(|(_, round)| round.phase == RoundState::Complete);
+    for (row_index, (index, round)) in complete_rounds.enumerate() {
+        let roster = rosters.get(index).unwrap_or(&no_teams);
+        let score_changes = game.settings.scoring.score_changes(round);
+        let participants: BTreeSet<&str> = round
+            .guesses
+            .iter()
+            .map(Guess::participant)
+            .chain(round.wagers.iter().map(Wager::participant))
+            .collect();
+        // Team members share the guess and wager their team submitted, whoever sent it
+        let players: BTreeSet<&str> = round
+            .guesses
+            .iter()
+            .map(|g| g.player.as_str())
+            .chain(round.wagers.iter().map(|w| w.player.as_str()))
+            .chain(
+                roster
+                    .iter()
+                    .filter(|(_, team)| participants.contains(team.as_str()))
+                    .map(|(player, _)| player.as_str()),
+            )
+            .collect();
+        for player in players {
+            let team = roster.get(player).map(String::as_str);
+            let participant = team.unwrap_or(player);
+            let guess = round
+                .guesses
+                .iter()
+                .find(|g| g.participant() == participant);
+            let wager = round.wagers.iter().find(|w| w.participant() == participant);
+            let row = ResultRow {
+                round: row_index + 1,
+                player,
+                team,
+                question: &round.question.question,
+                answer: round.question.answer,
+                guess: guess.map(|g| g.guess),
+                wager_target: wager.and_then(|w| w.guess),
+                wager_amount: wager.map(|w| w.wager),
+                score_change: score_changes.get(participant).copied().unwrap_or(0),
+            };
+            writer.serialize(row).expect("result row to serialize");
+        }
+    }
+    let bytes = writer.into_inner().expect("in memory writer to flush");
+    String::from_utf8(bytes).expect("csv to be utf8")
+}
+
+/// The team each player was on in every round, in the order the rounds were played. Taken from
+/// the game log since a member who leaves the submitting to their team never shows up in the
+/// round itself.
+pub(crate) fn team_rosters(events: &[RecordedEvent]) -> Vec<HashMap<Player, TeamName>> {
+    let mut rosters: Vec<HashMap<Player, TeamName>> = Vec::new();
+    let mut teams = HashMap::new();
+    for recorded in events {
+        match &recorded.event {
+            GameEvent::RoundStarted { .. } => rosters.push(teams.clone()),
+            GameEvent::TeamJoined { player, team } => {
+                teams.insert(player.clone(), team.clone());
+                if let Some(roster) = rosters.last_mut() {
+                    roster.insert(player.clone(), team.clone());
+                }
+            }
+            GameEvent::TeamLeft { player } => {
+                teams.remove(player);
+                if let Some(roster) = rosters.last_mut() {
+                    roster.remove(player);
+                }
+            }
+            // A player who leaves mid-round was still on the team for that round
+            GameEvent::PlayerLeft { player } => {
+                teams.remove(player);
+            }
+            _ => (),
+        }
+    }
+    rosters
+}
@@ -1,26 +1,56 @@
+mod bots;
+mod cli;
+mod config;
+mod export;
 mod question_lookup;
+mod rating;
+mod simulator;
+mod stats;
+mod store;
+mod strategy;
 #[cfg(test)]
 mod tests;
 mod types;
+mod validation;
 
+use bots::BotData;
+use cli::{load_config, load_questions, open_store, Command, Opt};
+use config::AppConfig;
+use export::{results_csv, GameExport};
 use question_lookup::QuestionLookup;
+use rating::RatingChange;
 use rocket::{
-    self,
-    config::LogLevel,
-    delete, get,
-    http::Method,
-    post, put, routes,
+    self, delete,
+    fairing::AdHoc,
+    get,
+    http::ContentType,
+    post, put,
+    response::stream::{Event, EventStream},
+    routes,
     serde::json::Json,
-    tokio::sync::{Mutex, RwLock},
-    Config, State,
+    tokio::{
+        self,
+        sync::{broadcast::error::RecvError, RwLock},
+    },
+    Build, Config, Rocket, State,
 };
-use rocket_cors::{AllowedOrigins, CorsOptions};
-use std::net::IpAddr;
-use std::path::PathBuf;
+use stats::{player_stats, PlayerStats, SeasonPoints, Standing};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use store::{NewProfileData, Profile, Store};
 use structopt::StructOpt;
-use types::{CreateGameData, Game, Guess, PlayerData, Result, Scores, Wager};
+use types::{
+    CreateGameData, Game, GameUpdateKind, Guess, LobbyData, NewGameData, Player, PlayerData,
+    ProfileCredentials, ProfileId, Question, RecordedEvent, Result, Scores, SessionData,
+    SpectatorData, SpectatorView, TeamData, Token, Wager,
+};
+
+type Games = Arc<types::Games>;
 
-type Games = Mutex<types::Games>;
+/// The longest the reaper waits between looking for idle games
+const MAX_REAP_INTERVAL: Duration = Duration::from_secs(60);
+/// How often an idle update stream is written to, which is how a closed one is noticed
+const LISTEN_HEARTBEAT: Duration = Duration::from_secs(10);
 type Questions = RwLock<QuestionLookup>;
 
 #[get("/heartbeat")]
@@ -34,43 +64,243 @@ async fn create_game(
     create_game_data: Json<CreateGameData>,
     games: &State<Games>,
     questions: &State<Questions>,
-) -> Result<()> {
-    let question = questions
-        .read()
-        .await
-        .get(create_game_data.get_questions_from)
-        .await;
-    let mut games = games.lock().await;
-    let player = create_game_data.player.clone();
-    games.create(
-        game_id,
-        player,
+    store: &State<Store>,
+    config: &State<AppConfig>,
+) -> Result<Json<SessionData>> {
+    let create_game_data = create_game_data.into_inner();
+    let get_questions_from = create_game_data
+        .get_questions_from
+        .unwrap_or(config.questions.source);
+    let question = questions.read().await.get(get_questions_from).await;
+    let profile = verify_profile(store, create_game_data.profile.as_ref())?;
+    let mut settings = config.game.clone();
+    create_game_data.settings.apply(&mut settings);
+    let session = games.create(
+        game_id.clone(),
+        create_game_data.player,
         question,
-        create_game_data.get_questions_from,
-    )
+        get_questions_from,
+        settings,
+    )?;
+    link_profile(games, &game_id, &session.player, profile)?;
+    Ok(Json(session))
+}
+
+#[post("/game", data = "<create_game_data>")]
+async fn create_game_with_code(
+    create_game_data: Json<CreateGameData>,
+    games: &State<Games>,
+    questions: &State<Questions>,
+    store: &State<Store>,
+    config: &State<AppConfig>,
+) -> Result<Json<NewGameData>> {
+    let create_game_data = create_game_data.into_inner();
+    let get_questions_from = create_game_data
+        .get_questions_from
+        .unwrap_or(config.questions.source);
+    let question = questions.read().await.get(get_questions_from).await;
+    let profile = verify_profile(store, create_game_data.profile.as_ref())?;
+    let mut settings = config.game.clone();
+    create_game_data.settings.apply(&mut settings);
+    let new_game = games.create_with_code(
+        create_game_data.player,
+        question,
+        get_questions_from,
+        settings,
+    )?;
+    link_profile(games, &new_game.game_id, &new_game.session.player, profile)?;
+    Ok(Json(new_game))
+}
+
+fn verify_profile(
+    store: &Store,
+    credentials: Option<&ProfileCredentials>,
+) -> Result<Option<ProfileId>> {
+    credentials.map(|c| store.verify(c)).transpose()
+}
+
+fn link_profile(
+    games: &Games,
+    game_id: &str,
+    player: &Player,
+    profile: Option<ProfileId>,
+) -> Result<()> {
+    match profile {
+        Some(profile) => games.update(game_id, |game| game.link_profile(player.clone(), profile)),
+        None => Ok(()),
+    }
+}
+
+/// Fetch a question for the round bots may be about to start, up front so the game is not
+/// locked while it is being looked up
+async fn next_question(games: &Games, game_id: &str, questions: &Questions) -> Result<Question> {
+    let question_location = games.read(game_id, |game| game.question_location)?;
+    Ok(questions.read().await.get(question_location).await)
+}
+
+#[get("/games")]
+async fn lobbies(games: &State<Games>, store: &State<Store>) -> Json<Vec<LobbyData>> {
+    Json(games.lobbies(|profile| store.profile(profile).ok().map(|p| p.rating)))
 }
 
 #[post("/game/<game_id>", data = "<player>")]
-async fn join_game(game_id: String, player: Json<PlayerData>, games: &State<Games>) -> Result<()> {
-    let mut games = games.lock().await;
-    let game = games.get(&game_id)?;
+async fn join_game(
+    game_id: String,
+    player: Json<PlayerData>,
+    games: &State<Games>,
+    store: &State<Store>,
+) -> Result<Json<SessionData>> {
     let player = player.into_inner();
-    game.add_player(player.player)
+    let profile = verify_profile(store, player.profile.as_ref())?;
+    games
+        .update(&game_id, |game| {
+            let session = game.add_player(player.player)?;
+            if let Some(profile) = profile {
+                game.link_profile(session.player.clone(), profile)?;
+            }
+            Ok(session)
+        })
+        .map(Json)
+}
+
+#[post("/game/<game_id>/reconnect", data = "<session>")]
+async fn reconnect(
+    game_id: String,
+    session: Json<SessionData>,
+    games: &State<Games>,
+) -> Result<()> {
+    games.read(&game_id, |game| game.reconnect(&session))?
 }
 
 #[get("/game/<game_id>")]
 async fn game(game_id: String, games: &State<Games>) -> Result<Json<Game>> {
-    let mut games = games.lock().await;
-    let game = games.get(&game_id)?;
-    Ok(Json(game.clone()))
+    games.read(&game_id, |game| Json(game.clone()))
+}
+
+#[get("/game/<game_id>/events")]
+async fn events(game_id: String, games: &State<Games>) -> Result<Json<Vec<RecordedEvent>>> {
+    games.read(&game_id, |game| Json(game.events.clone()))
+}
+
+/// The game as it was after its first `until` events, or all of them if `until` is not given
+#[get("/game/<game_id>/replay?<until>")]
+async fn replay(game_id: String, until: Option<usize>, games: &State<Games>) -> Result<Json<Game>> {
+    games.read(&game_id, |game| {
+        let until = until.unwrap_or(game.events.len());
+        let events = game.events.iter().take(until).cloned();
+        Json(Game::replay(events))
+    })
+}
+
+#[get("/game/<game_id>/export")]
+async fn export_game(game_id: String, games: &State<Games>) -> Result<Json<GameExport>> {
+    games.read(&game_id, |game| {
+        Json(GameExport::new(game_id.clone(), game))
+    })
+}
+
+#[get("/game/<game_id>/export.csv")]
+async fn export_game_csv(game_id: String, games: &State<Games>) -> Result<(ContentType, String)> {
+    games.read(&game_id, |game| (ContentType::CSV, results_csv(game)))
+}
+
+/// Load an exported game to replay or resume it, every player is handed a new token
+#[put("/game/<game_id>/import", data = "<export>")]
+async fn import_game(
+    game_id: String,
+    export: Json<GameExport>,
+    games: &State<Games>,
+) -> Result<Json<Vec<SessionData>>> {
+    let mut game = export.into_inner().into_game()?;
+    let sessions = game.reissue_tokens();
+    games.insert(game_id, game)?;
+    Ok(Json(sessions))
+}
+
+/// Stream every update to the game. A player who gives their session shows as connected for as
+/// long as the stream is open, the session goes in the query since browsers cannot send a body.
+#[get("/game/<game_id>/listen?<player>&<token>")]
+async fn listen(
+    game_id: String,
+    player: Option<Player>,
+    token: Option<Token>,
+    games: &State<Games>,
+) -> Result<EventStream![]> {
+    let listener = player
+        .zip(token)
+        .map(|(player, token)| games.listen(&game_id, &SessionData { player, token }))
+        .transpose()?;
+    let mut updates = games.subscribe(&game_id)?;
+    Ok(EventStream! {
+        // Dropped along with the stream once the client goes away
+        let _listener = listener;
+        loop {
+            match updates.recv().await {
+                Ok(update) => {
+                    yield Event::json(&update.game).event(update.kind.to_string());
+                    // The game is gone, nothing more will be sent
+                    if matches!(update.kind, GameUpdateKind::Expired | GameUpdateKind::Deleted) {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                // The game is gone and its final state has already been sent
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+    .heartbeat(LISTEN_HEARTBEAT))
+}
+
+#[post("/game/<game_id>/spectate", data = "<spectator>")]
+async fn spectate_game(
+    game_id: String,
+    spectator: Json<SpectatorData>,
+    games: &State<Games>,
+) -> Result<()> {
+    let spectator = spectator.into_inner();
+    games.update(&game_id, |game| game.add_spectator(spectator.spectator))
+}
+
+#[get("/game/<game_id>/spectate")]
+async fn spectator_view(game_id: String, games: &State<Games>) -> Result<Json<SpectatorView>> {
+    games.read(&game_id, |game| Json(game.spectator_view()))
+}
+
+#[delete("/game/<game_id>/spectate", data = "<spectator>")]
+async fn stop_spectating(
+    game_id: String,
+    spectator: Json<SpectatorData>,
+    games: &State<Games>,
+) -> Result<()> {
+    let spectator = spectator.into_inner();
+    games.update(&game_id, |game| {
+        game.remove_spectator(&spectator.spectator);
+        Ok(())
+    })
+}
+
+#[post("/game/<game_id>/team", data = "<team>")]
+async fn join_team(game_id: String, team: Json<TeamData>, games: &State<Games>) -> Result<()> {
+    let team = team.into_inner();
+    games.update(&game_id, |game| game.join_team(team.player, team.team))
+}
+
+#[delete("/game/<game_id>/team", data = "<player>")]
+async fn exit_team(game_id: String, player: Json<PlayerData>, games: &State<Games>) -> Result<()> {
+    let player = player.into_inner();
+    games.update(&game_id, |game| game.exit_team(&player.player))
 }
 
 #[post("/game/<game_id>/guess", data = "<guess>")]
 async fn guess(game_id: String, guess: Json<Guess>, games: &State<Games>) -> Result<()> {
-    let mut games = games.lock().await;
-    let game = games.get(&game_id)?;
     let guess = guess.into_inner();
-    game.guess(guess)
+    games.update(&game_id, |game| {
+        game.guess(guess)?;
+        // Bots may be waiting on this guess, but a guess never completes a round
+        game.play_bots(None);
+        Ok(())
+    })
 }
 
 #[post("/game/<game_id>/wager", data = "<wager>")]
@@ -80,114 +310,235 @@ async fn wager(
     games: &State<Games>,
     questions: &State<Questions>,
 ) -> Result<()> {
-    let mut games = games.lock().await;
-    let game = games.get(&game_id)?;
-    let question = questions.read().await.get(game.question_location).await;
+    let question = next_question(games, &game_id, questions).await?;
     let wager = wager.into_inner();
-    game.wager(wager)?;
-    game.add_round_if_complete(question);
-    Ok(())
+    games.update(&game_id, |game| {
+        game.wager(wager)?;
+        game.play_bots(Some(question));
+        Ok(())
+    })
 }
 
 #[delete("/game/<game_id>/exit", data = "<player>")]
-async fn exit_game(game_id: String, player: Json<PlayerData>, games: &State<Games>) -> Result<()> {
-    let mut games = games.lock().await;
-    let game = games.get(&game_id)?;
+async fn exit_game(
+    game_id: String,
+    player: Json<PlayerData>,
+    games: &State<Games>,
+    questions: &State<Questions>,
+) -> Result<()> {
+    let question = next_question(games, &game_id, questions).await?;
     let player = player.into_inner();
-    game.remove_player(player.player)
+    games.update(&game_id, |game| {
+        // The player leaving may have been the last one the round was waiting on
+        game.remove_player(player.player)?;
+        game.play_bots(Some(question));
+        Ok(())
+    })
+}
+
+#[post("/game/<game_id>/bot", data = "<bot>")]
+async fn add_bot(
+    game_id: String,
+    bot: Json<BotData>,
+    games: &State<Games>,
+    questions: &State<Questions>,
+) -> Result<()> {
+    let question = next_question(games, &game_id, questions).await?;
+    let bot = bot.into_inner();
+    games.update(&game_id, |game| {
+        game.add_bot(bot.player, bot.difficulty)?;
+        game.play_bots(Some(question));
+        Ok(())
+    })
+}
+
+/// End the game and keep it so its results count towards player stats, only a player in the
+/// game can end it
+#[post("/game/<game_id>/finish", data = "<session>")]
+async fn finish_game(
+    game_id: String,
+    session: Json<SessionData>,
+    games: &State<Games>,
+    store: &State<Store>,
+) -> Result<()> {
+    let export = games.update(&game_id, |game| {
+        game.check_session(&session)?;
+        game.finish();
+        Ok(GameExport::new(game_id.clone(), game))
+    })?;
+    store.archive_game(export)
+}
+
+#[post("/profile", data = "<new_profile>")]
+async fn create_profile(
+    new_profile: Json<NewProfileData>,
+    store: &State<Store>,
+) -> Result<Json<ProfileCredentials>> {
+    let new_profile = new_profile.into_inner();
+    store.create_profile(new_profile.name).map(Json)
+}
+
+#[get("/profile/<profile_id>")]
+async fn profile(profile_id: String, store: &State<Store>) -> Result<Json<Profile>> {
+    store.profile(&profile_id).map(Json)
+}
+
+#[get("/profile/<profile_id>/stats")]
+async fn profile_stats(profile_id: String, store: &State<Store>) -> Result<Json<PlayerStats>> {
+    store.profile(&profile_id)?;
+    Ok(Json(store.games(|games| player_stats(&profile_id, games))))
+}
+
+#[get("/profile/<profile_id>/ratings")]
+async fn rating_history(
+    profile_id: String,
+    store: &State<Store>,
+) -> Result<Json<Vec<RatingChange>>> {
+    store.rating_history(&profile_id).map(Json)
+}
+
+#[get("/season/<season>/leaderboard")]
+async fn leaderboard(
+    season: String,
+    store: &State<Store>,
+    points: &State<SeasonPoints>,
+) -> Json<Vec<Standing>> {
+    Json(store.leaderboard(&season, points))
 }
 
 #[delete("/game/<game_id>")]
 async fn delete_game(game_id: String, games: &State<Games>) {
-    let mut games = games.lock().await;
     games.delete(&game_id)
 }
 
 #[get("/game/<game_id>/score")]
 async fn get_score(game_id: String, games: &State<Games>) -> Result<Json<Scores>> {
-    let mut games = games.lock().await;
-    let game = games.get(&game_id)?.clone();
-    Ok(Json(game.get_score()))
+    games.read(&game_id, |game| Json(game.get_score()))
 }
 
 #[get("/game/<game_id>/round_score")]
 async fn get_round_score(game_id: String, games: &State<Games>) -> Result<Json<Scores>> {
-    let mut games = games.lock().await;
-    let game = games.get(&game_id)?.clone();
-    let round = game.rounds.get(game.rounds.len() - 2);
-    match round {
-        None => Ok(Json(Scores::new())),
-        Some(round) => Ok(Json(round.get_score_changes(3, 3))),
+    games.read(&game_id, |game| {
+        let round = game.rounds.get(game.rounds.len() - 2);
+        match round {
+            None => Json(Scores::new()),
+            Some(round) => Json(game.settings.scoring.score_changes(round)),
+        }
+    })
+}
+
+async fn reap_idle_games(games: Games, ttl: Duration) {
+    let mut interval = tokio::time::interval(ttl.min(MAX_REAP_INTERVAL));
+    loop {
+        interval.tick().await;
+        let reaped = games.reap(Instant::now(), ttl);
+        for game_id in reaped {
+            log::info!(
+                "Deleted game {game_id} after {} seconds idle",
+                ttl.as_secs()
+            );
+        }
     }
 }
 
-#[derive(Debug, StructOpt)]
-struct Opt {
-    /// The path to a file containing newline delimited questions.
-    #[structopt(long = "questions-file")]
-    questions_file: Option<PathBuf>,
-    /// An IP address the application will listen on.
-    #[structopt(long = "host", short = "H", default_value = "0.0.0.0")]
-    address: IpAddr,
-    /// A port number to listen on.
-    #[structopt(long = "port", short = "P", default_value = "8172")]
-    port: u16,
-    /// The log level.
-    #[structopt(
-        default_value = "normal",
-        long = "log-level",
-        possible_values = &["off", "debug", "normal", "critical"]
-    )]
-    log_level: LogLevel,
-}
-
-#[rocket::launch]
-fn rocket() -> _ {
+#[rocket::main]
+async fn main() {
     let opt = Opt::from_args();
-    let config = Config {
-        address: opt.address,
-        port: opt.port,
-        log_level: opt.log_level,
+    let mut config = load_config(&opt);
+    let store = open_store(config.storage.data_dir.as_ref());
+    // Running without a subcommand serves, as it did before there were other commands
+    match opt.command.unwrap_or_default() {
+        Command::Serve(serve_opt) => {
+            opt.serve.apply(&mut config.server);
+            serve_opt.apply(&mut config.server);
+            let questions = load_questions(config.questions.file.as_ref(), &store);
+            if let Err(e) = rocket(config, questions, store).launch().await {
+                eprintln!("Failed to serve, err: {e}");
+                std::process::exit(1);
+            }
+        }
+        Command::ValidateQuestions { file } => cli::validate_questions(&file),
+        Command::ImportQuestions { file } => {
+            cli::import_questions(&file, &store, config.storage.data_dir.as_ref())
+        }
+        Command::ExportGame { game_id, csv } => cli::export_game(&game_id, csv, &store),
+        Command::ListGames => cli::list_games(&store),
+        Command::Simulate(simulate_opt) => {
+            let questions = load_questions(config.questions.file.as_ref(), &store);
+            cli::run_simulation(simulate_opt, &questions);
+        }
+    }
+}
+
+fn rocket(config: AppConfig, questions: QuestionLookup, store: Store) -> Rocket<Build> {
+    let rocket_config = Config {
+        address: config.server.address,
+        port: config.server.port,
+        log_level: config.server.log_level,
         ..Config::default()
     };
 
-    // Populate the questions
-    let mut questions = QuestionLookup::default();
-    if let Some(questions_file) = opt.questions_file {
-        if let Err(e) = questions.populate_from_file(&questions_file) {
-            eprintln!("Failed to populate questions from file {questions_file:?}, err: {e}");
-            std::process::exit(1);
-        }
-    }
+    let cors = config
+        .server
+        .cors
+        .to_cors()
+        .expect("cors settings to be checked when the config was loaded");
 
-    let cors = CorsOptions::default()
-        .allowed_origins(AllowedOrigins::all())
-        .allowed_methods(
-            vec![Method::Get, Method::Post, Method::Patch, Method::Put]
-                .into_iter()
-                .map(From::from)
-                .collect(),
-        )
-        .allow_credentials(true);
+    let games = Games::default();
+    let game_ttl = Duration::from_secs(config.server.game_ttl);
+    let reaper_games = Arc::clone(&games);
+    let reaper = AdHoc::on_liftoff("Idle Game Reaper", move |_| {
+        Box::pin(async move {
+            // A ttl of 0 keeps idle games forever
+            if !game_ttl.is_zero() {
+                tokio::spawn(reap_idle_games(reaper_games, game_ttl));
+            }
+        })
+    });
 
     rocket::build()
-        .configure(config)
-        .attach(cors.to_cors().expect("known valid cors config"))
+        .configure(rocket_config)
+        .attach(cors)
+        .attach(reaper)
         .mount(
             "/api/v1",
             routes![
                 heartbeat,
                 create_game,
+                create_game_with_code,
+                lobbies,
                 join_game,
+                reconnect,
                 game,
+                events,
+                replay,
+                export_game,
+                export_game_csv,
+                import_game,
+                listen,
+                spectate_game,
+                spectator_view,
+                stop_spectating,
+                join_team,
+                exit_team,
                 guess,
                 wager,
                 exit_game,
+                add_bot,
+                finish_game,
                 delete_game,
                 get_score,
                 get_round_score,
+                create_profile,
+                profile,
+                profile_stats,
+                rating_history,
+                leaderboard,
             ],
         )
         .manage(Questions::new(questions))
-        .manage(Games::default())
+        .manage(games)
+        .manage(store)
+        .manage(SeasonPoints(config.server.season_points.clone()))
+        .manage(config)
 }
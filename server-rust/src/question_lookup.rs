@@ -7,7 +7,7 @@ use std::{
     sync::atomic::{AtomicUsize, Ordering},
 };
 
-use crate::types::{GetQuestionLocation, Question};
+use crate::types::{AnswerAmount, GetQuestionLocation, Question};
 
 const DEFAULT_QUESTION: &str = "What question would you like to be asked?";
 
@@ -28,20 +28,13 @@ struct NumbersApiResponse {
 
 impl QuestionLookup {
     pub(crate) fn populate_from_file(&mut self, path: &Path) -> io::Result<()> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        for line in reader.lines() {
-            let line = line?;
-            let values: Vec<&str> = line.split(',').collect();
-            self.questions.push(Question {
-                question: values[0].to_string(),
-                answer: values[1]
-                    .parse()
-                    .expect(&format!("value after comma should be a number: {}", line)),
-            });
-            let mut rng = rand::thread_rng();
-            self.questions.shuffle(&mut rng);
+        for (index, line) in read_lines(path)?.into_iter().enumerate() {
+            let question = parse_question(&line)
+                .map_err(|e| io::Error::new(e.kind(), format!("line {}: {e}", index + 1)))?;
+            self.questions.push(question);
         }
+        let mut rng = rand::thread_rng();
+        self.questions.shuffle(&mut rng);
         Ok(())
     }
 
@@ -64,6 +57,9 @@ impl QuestionLookup {
             return Question {
                 question: String::from(DEFAULT_QUESTION),
                 answer: 0,
+                category: None,
+                min: None,
+                max: None,
             };
         }
         let index = self
@@ -82,6 +78,80 @@ impl QuestionLookup {
     }
 }
 
+/// Every line of a questions file
+pub(crate) fn read_lines(path: &Path) -> io::Result<Vec<String>> {
+    BufReader::new(File::open(path)?).lines().collect()
+}
+
+/// Read a line of a questions file, the question and its answer separated by a comma followed
+/// by an optional category, lowest guess and highest guess. Any of the optional values can be
+/// left empty.
+pub(crate) fn parse_question(line: &str) -> io::Result<Question> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let values: Vec<&str> = line.split(',').collect();
+    if values.len() < 2 || values.len() > 5 {
+        return Err(invalid(format!(
+            "expected a question, an answer and an optional category, lowest guess and highest \
+             guess separated by commas: {line}"
+        )));
+    }
+    if values[0].trim().is_empty() {
+        return Err(invalid(format!("the question is empty: {line}")));
+    }
+    let answer = values[1]
+        .trim()
+        .parse()
+        .map_err(|_| invalid(format!("value after comma should be a number: {line}")))?;
+    let optional = |index: usize| {
+        values
+            .get(index)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    };
+    let bound = |index: usize, name: &str| {
+        optional(index)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| invalid(format!("the {name} guess should be a number: {line}")))
+            })
+            .transpose()
+    };
+    let question = Question {
+        question: values[0].to_string(),
+        answer,
+        category: optional(2).map(String::from),
+        min: bound(3, "lowest")?,
+        max: bound(4, "highest")?,
+    };
+    let (min, max) = question.guess_range();
+    if !(min..=max).contains(&question.answer) {
+        return Err(invalid(format!(
+            "the answer should be between the lowest and highest guess: {line}"
+        )));
+    }
+    Ok(question)
+}
+
+/// Write a question as a line of a questions file
+pub(crate) fn format_question(question: &Question) -> String {
+    let category = question.category.as_deref().unwrap_or_default();
+    let bound = |bound: Option<AnswerAmount>| bound.map(|b| b.to_string()).unwrap_or_default();
+    match (question.min, question.max) {
+        (None, None) if category.is_empty() => {
+            format!("{},{}", question.question, question.answer)
+        }
+        (None, None) => format!("{},{},{category}", question.question, question.answer),
+        (min, max) => format!(
+            "{},{},{category},{},{}",
+            question.question,
+            question.answer,
+            bound(min),
+            bound(max)
+        ),
+    }
+}
+
 async fn get_question_from_numbers_api() -> Result<Question, reqwest::Error> {
     let numbers_api_response: NumbersApiResponse =
         reqwest::get("http://numbersapi.com/random/trivia?json")
@@ -95,5 +165,8 @@ async fn get_question_from_numbers_api() -> Result<Question, reqwest::Error> {
     Ok(Question {
         question,
         answer: numbers_api_response.number,
+        category: Some(numbers_api_response.r#type),
+        min: None,
+        max: None,
     })
 }
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::GameId;
+
+pub(crate) type Rating = f64;
+
+/// The rating every profile starts with
+pub(crate) const INITIAL_RATING: Rating = 1500.0;
+/// The most a rating can move in one game
+const K_FACTOR: Rating = 32.0;
+
+/// A profile's rating after a finished game
+#[derive(Clone, Deserialize, Serialize)]
+pub(crate) struct RatingChange {
+    /// The game that changed the rating
+    pub game_id: GameId,
+    /// When the game finished, in seconds since the unix epoch
+    pub at: u64,
+    /// The rating after the game
+    pub rating: Rating,
+}
+
+/// Work out new ratings for everyone in a finished game from their ratings going in and the place
+/// they finished in. Each player is scored as a win, draw or loss against every other player, so a
+/// game of any size moves ratings by at most `K_FACTOR`.
+pub(crate) fn rate(players: &[(Rating, usize)]) -> Vec<Rating> {
+    if players.len() < 2 {
+        return players.iter().map(|(rating, _)| *rating).collect();
+    }
+    let opponents = (players.len() - 1) as Rating;
+    players
+        .iter()
+        .enumerate()
+        .map(|(i, (rating, place))| {
+            let change: Rating = players
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, (other_rating, other_place))| {
+                    let expected = 1.0 / (1.0 + 10_f64.powf((other_rating - rating) / 400.0));
+                    let actual = match place.cmp(other_place) {
+                        std::cmp::Ordering::Less => 1.0,
+                        std::cmp::Ordering::Equal => 0.5,
+                        std::cmp::Ordering::Greater => 0.0,
+                    };
+                    actual - expected
+                })
+                .sum();
+            rating + K_FACTOR * change / opponents
+        })
+        .collect()
+}
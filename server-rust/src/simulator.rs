@@ -0,0 +1,226 @@
+use std::fmt;
+
+use crate::{
+    question_lookup::QuestionLookup,
+    strategy::{Contender, RoundView},
+    types::{
+        Game, GameSettings, GetQuestionLocation, Guess, Player, ScoreAmount, ScoringRules, Wager,
+    },
+};
+
+/// How the simulated games are played
+pub(crate) struct Simulation {
+    /// The number of games to play
+    pub games: usize,
+    /// The most rounds a game lasts
+    pub max_rounds: usize,
+    /// A game ends early once someone reaches this score
+    pub target_score: Option<ScoreAmount>,
+    /// How rounds are scored
+    pub scoring: ScoringRules,
+}
+
+/// How one strategy did over a simulation
+pub(crate) struct StrategyResult {
+    /// The name the strategy reports results under
+    pub name: String,
+    /// The number of games won, a win shared between tied players is split between them
+    pub wins: f64,
+    /// The final score of every game played, lowest first
+    pub scores: Vec<ScoreAmount>,
+}
+
+impl StrategyResult {
+    pub(crate) fn average_score(&self) -> f64 {
+        let total: f64 = self.scores.iter().copied().map(f64::from).sum();
+        total / self.scores.len().max(1) as f64
+    }
+
+    /// The final score the given share of games finished at or below
+    pub(crate) fn percentile(&self, share: f64) -> ScoreAmount {
+        if self.scores.is_empty() {
+            return 0;
+        }
+        let index = (share * (self.scores.len() - 1) as f64).round() as usize;
+        self.scores[index]
+    }
+}
+
+/// The results of playing many games between strategies
+pub(crate) struct SimulationReport {
+    /// The number of games played
+    pub games: usize,
+    /// How each strategy did, in the order they were given
+    pub results: Vec<StrategyResult>,
+    /// The number of games won by someone who was not leading halfway through
+    pub comebacks: usize,
+    /// The number of rounds played over every game
+    pub rounds: usize,
+}
+
+impl fmt::Display for SimulationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let games = self.games.max(1) as f64;
+        writeln!(f, "{} games", self.games)?;
+        writeln!(
+            f,
+            "Average game length {:.1} rounds",
+            self.rounds as f64 / games
+        )?;
+        writeln!(f, "Comebacks {:.1}%", 100.0 * self.comebacks as f64 / games)?;
+        writeln!(
+            f,
+            "{:<12} {:>8} {:>8} {:>6} {:>6} {:>6} {:>6} {:>6}",
+            "player", "win rate", "average", "min", "p25", "median", "p75", "max"
+        )?;
+        for result in &self.results {
+            writeln!(
+                f,
+                "{:<12} {:>7.1}% {:>8.1} {:>6} {:>6} {:>6} {:>6} {:>6}",
+                result.name,
+                100.0 * result.wins / games,
+                result.average_score(),
+                result.percentile(0.0),
+                result.percentile(0.25),
+                result.percentile(0.5),
+                result.percentile(0.75),
+                result.percentile(1.0),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// What happened in a single simulated game
+struct GameResult {
+    /// The final score of each strategy
+    scores: Vec<ScoreAmount>,
+    /// The score of each strategy halfway through the game
+    halfway_scores: Vec<ScoreAmount>,
+    /// The number of rounds played
+    rounds: usize,
+}
+
+/// Play games with one seat per strategy and questions from the question bank
+pub(crate) fn simulate(
+    strategies: &[Contender],
+    questions: &QuestionLookup,
+    simulation: &Simulation,
+) -> SimulationReport {
+    let mut report = SimulationReport {
+        games: simulation.games,
+        results: strategies
+            .iter()
+            .map(|strategy| StrategyResult {
+                name: strategy.name(),
+                wins: 0.0,
+                scores: Vec::with_capacity(simulation.games),
+            })
+            .collect(),
+        comebacks: 0,
+        rounds: 0,
+    };
+    for _ in 0..simulation.games {
+        let game = play_game(strategies, questions, simulation);
+        report.rounds += game.rounds;
+        let top_score = game.scores.iter().copied().max().unwrap_or(0);
+        let halfway_top_score = game.halfway_scores.iter().copied().max().unwrap_or(0);
+        let winners = game
+            .scores
+            .iter()
+            .filter(|score| **score == top_score)
+            .count();
+        let mut comeback = false;
+        for (index, result) in report.results.iter_mut().enumerate() {
+            if game.scores[index] == top_score {
+                result.wins += 1.0 / winners as f64;
+                comeback |= game.halfway_scores[index] < halfway_top_score;
+            }
+            result.scores.push(game.scores[index]);
+        }
+        if comeback {
+            report.comebacks += 1;
+        }
+    }
+    for result in &mut report.results {
+        result.scores.sort_unstable();
+    }
+    report
+}
+
+/// Play a single game until it runs out of rounds or someone reaches the target score
+fn play_game(
+    strategies: &[Contender],
+    questions: &QuestionLookup,
+    simulation: &Simulation,
+) -> GameResult {
+    let mut rng = rand::thread_rng();
+    let settings = GameSettings {
+        scoring: simulation.scoring,
+        ..Default::default()
+    };
+    let mut game = Game::new(GetQuestionLocation::File, settings);
+    game.add_round_if_complete(questions.get_from_file());
+    // Number the seats so the same strategy can play itself
+    let seats: Vec<Player> = strategies
+        .iter()
+        .enumerate()
+        .map(|(index, strategy)| format!("{} {}", strategy.name(), index + 1))
+        .collect();
+    for seat in &seats {
+        game.add_player(seat.clone())
+            .expect("seat names to be unique");
+    }
+    let seat_scores = |game: &Game| -> Vec<ScoreAmount> {
+        let scores = game.get_score();
+        seats.iter().map(|seat| scores[seat]).collect()
+    };
+    let mut history = vec![seat_scores(&game)];
+    for round in 0..simulation.max_rounds {
+        if round > 0 {
+            game.add_round_if_complete(questions.get_from_file());
+        }
+        for (seat, strategy) in seats.iter().zip(strategies) {
+            let view = RoundView::new(&game, seat);
+            let answer = game.current_round().question.answer;
+            let guess = strategy.guess(&view, answer, &mut rng);
+            let guess = game.current_round().question.clamp_guess(guess);
+            game.guess(Guess {
+                player: seat.clone(),
+                guess,
+                team: None,
+            })
+            .expect("every seat to be able to guess");
+        }
+        for (seat, strategy) in seats.iter().zip(strategies) {
+            let view = RoundView::new(&game, seat);
+            let (guess, wager) = strategy.wager(&view, &mut rng);
+            // Keep a strategy that asks for something impossible in the game instead of failing
+            let guess = guess.filter(|guess| view.guesses.contains(*guess));
+            let (min_wager, max_wager) = simulation.scoring.wager_range(view.bankroll);
+            let wager = wager.clamp(min_wager, max_wager);
+            game.wager(Wager {
+                player: seat.clone(),
+                guess,
+                wager,
+                team: None,
+                all_in: false,
+            })
+            .expect("a clamped wager to be valid");
+        }
+        let scores = seat_scores(&game);
+        let reached_target = simulation
+            .target_score
+            .is_some_and(|target| scores.iter().any(|score| *score >= target));
+        history.push(scores);
+        if reached_target {
+            break;
+        }
+    }
+    let rounds = history.len() - 1;
+    GameResult {
+        scores: history[rounds].clone(),
+        halfway_scores: history[rounds / 2].clone(),
+        rounds,
+    }
+}
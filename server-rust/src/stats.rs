@@ -0,0 +1,209 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    export::{team_rosters, GameExport},
+    types::{GameEvent, Player, ProfileId, Round, RoundState, ScoreAmount},
+};
+
+/// The fewest people, not counting bots or extra seats played under the same profile, a finished
+/// game needs for its result to count
+pub(crate) const MIN_COUNTED_PLAYERS: usize = 2;
+
+/// If the game was a real contest whose result counts towards stats, standings and ratings:
+/// enough people took part and at least one round was played to the end
+pub(crate) fn counts(game: &GameExport) -> bool {
+    let bots: HashSet<&Player> = game
+        .events
+        .iter()
+        .filter_map(|recorded| match &recorded.event {
+            GameEvent::BotAdded { player, .. } => Some(player),
+            _ => None,
+        })
+        .collect();
+    // Seats sharing a profile are one person
+    let mut profiles = HashSet::new();
+    let people = game
+        .players
+        .iter()
+        .filter(|player| !bots.contains(player))
+        .filter(|player| match game.profiles.get(*player) {
+            Some(profile) => profiles.insert(profile),
+            None => true,
+        })
+        .count();
+    people >= MIN_COUNTED_PLAYERS
+        && game
+            .rounds
+            .iter()
+            .any(|round| round.phase == RoundState::Complete)
+}
+
+/// How a profile has done over every finished game it played in
+#[derive(Default, Serialize)]
+pub(crate) struct PlayerStats {
+    /// The number of finished games played
+    pub games_played: usize,
+    /// The number of games finished with the top score, ties included
+    pub wins: usize,
+    /// How far guesses were from the answer on average, as a percentage of the answer
+    pub average_guess_error: Option<f64>,
+    /// The share of wagers placed on the winning guess
+    pub wager_accuracy: Option<f64>,
+    /// The most won in a single round
+    pub biggest_payout: ScoreAmount,
+    /// The category of the questions guessed on most often
+    pub favourite_category: Option<String>,
+}
+
+pub(crate) fn player_stats(profile: &str, games: &[GameExport]) -> PlayerStats {
+    let mut stats = PlayerStats::default();
+    let mut guess_errors = Vec::new();
+    let (mut wagers, mut correct_wagers) = (0, 0);
+    let mut categories: HashMap<String, usize> = HashMap::new();
+    for game in games.iter().filter(|game| counts(game)) {
+        let rosters = team_rosters(&game.events);
+        if let Some(player) = seat_of(game, profile) {
+            stats.games_played += 1;
+            for (index, round) in game.rounds.iter().enumerate() {
+                if round.phase != RoundState::Complete {
+                    continue;
+                }
+                let round = Round {
+                    question: round.question.clone(),
+                    guesses: round.guesses.clone(),
+                    wagers: round.wagers.clone(),
+                    phase: round.phase,
+                    board: round.guesses.board(),
+                };
+                // Team members are credited with what their team submitted
+                let participant = rosters
+                    .get(index)
+                    .and_then(|roster| roster.get(player))
+                    .unwrap_or(player);
+                if let Some(guess) = round
+                    .guesses
+                    .iter()
+                    .find(|g| g.participant() == participant)
+                {
+                    let answer = round.question.answer;
+                    if answer > 0 {
+                        let error = f64::from(guess.guess.abs_diff(answer)) / f64::from(answer);
+                        guess_errors.push(error * 100.0);
+                    }
+                    if let Some(category) = &round.question.category {
+                        *categories.entry(category.clone()).or_default() += 1;
+                    }
+                }
+                if let Some(wager) = round.wagers.iter().find(|w| w.participant() == participant) {
+                    wagers += 1;
+                    if wager.guess == round.get_closest_guess() {
+                        correct_wagers += 1;
+                    }
+                }
+                let score_change = game.settings.scoring.score_changes(&round);
+                if let Some(&payout) = score_change.get(participant) {
+                    stats.biggest_payout = stats.biggest_payout.max(payout);
+                }
+            }
+            if placing(game, player) == Some(1) {
+                stats.wins += 1;
+            }
+        }
+    }
+    if !guess_errors.is_empty() {
+        stats.average_guess_error =
+            Some(guess_errors.iter().sum::<f64>() / guess_errors.len() as f64);
+    }
+    if wagers > 0 {
+        stats.wager_accuracy = Some(f64::from(correct_wagers) / f64::from(wagers));
+    }
+    stats.favourite_category = categories
+        .into_iter()
+        .max_by(|(a_name, a_count), (b_name, b_count)| {
+            // Break ties by name so the answer does not depend on map order
+            a_count.cmp(b_count).then(b_name.cmp(a_name))
+        })
+        .map(|(category, _)| category);
+    stats
+}
+
+/// The ranking points handed out by final placing, first place first
+pub(crate) struct SeasonPoints(pub Vec<u32>);
+
+/// Where a profile stands in a season
+#[derive(Serialize)]
+pub(crate) struct Standing {
+    /// The profile the standing is for
+    pub profile: ProfileId,
+    /// The name of the profile
+    pub name: String,
+    /// The ranking points earned over the season
+    pub points: u32,
+    /// The number of finished games played in the season
+    pub games_played: usize,
+}
+
+/// Rank every profile that finished a game tagged with the season, best first
+pub(crate) fn season_standings(
+    season: &str,
+    games: &[GameExport],
+    points: &SeasonPoints,
+) -> Vec<Standing> {
+    let mut standings: HashMap<&ProfileId, Standing> = HashMap::new();
+    let season_games = games
+        .iter()
+        .filter(|game| game.settings.season.as_deref() == Some(season) && counts(game));
+    for game in season_games {
+        let profiles: HashSet<&ProfileId> = game.profiles.values().collect();
+        for profile in profiles {
+            let standing = standings.entry(profile).or_insert_with(|| Standing {
+                profile: profile.clone(),
+                name: String::new(),
+                points: 0,
+                games_played: 0,
+            });
+            standing.games_played += 1;
+            standing.points += seat_of(game, profile)
+                .and_then(|player| placing(game, player))
+                .and_then(|place| points.0.get(place - 1))
+                .copied()
+                .unwrap_or(0);
+        }
+    }
+    let mut standings: Vec<Standing> = standings.into_values().collect();
+    standings.sort_by(|a, b| {
+        b.points
+            .cmp(&a.points)
+            .then(a.games_played.cmp(&b.games_played))
+            .then(a.profile.cmp(&b.profile))
+    });
+    standings
+}
+
+/// The seat a profile is credited with in a game. A profile can only be linked to one seat, but
+/// games archived before that was enforced may have it on several, and then its best finish
+/// counts.
+pub(crate) fn seat_of<'a>(game: &'a GameExport, profile: &str) -> Option<&'a Player> {
+    game.profiles
+        .iter()
+        .filter(|(_, linked)| linked.as_str() == profile)
+        .map(|(player, _)| player)
+        .min_by_key(|player| (placing(game, player).unwrap_or(usize::MAX), *player))
+}
+
+/// The place a player finished a game in, players on a team share its place and tied scores
+/// share the higher place. Games that do not count have no placings.
+pub(crate) fn placing(game: &GameExport, player: &str) -> Option<usize> {
+    if !counts(game) {
+        return None;
+    }
+    // A player finishes with the team they were on in the last round
+    let rosters = team_rosters(&game.events);
+    let participant = rosters
+        .last()
+        .and_then(|roster| roster.get(player))
+        .map_or(player, String::as_str);
+    let score = game.scores.get(participant)?;
+    Some(1 + game.scores.values().filter(|other| *other > score).count())
+}
@@ -0,0 +1,252 @@
+use parking_lot::RwLock;
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    export::GameExport,
+    question_lookup::{format_question, parse_question, read_lines},
+    rating::{rate, Rating, RatingChange, INITIAL_RATING},
+    stats::{placing, season_standings, seat_of, SeasonPoints, Standing},
+    types::{generate_token, Error, ProfileCredentials, ProfileId, Question, Result, Token},
+    validation,
+};
+
+const PROFILES_FILE: &str = "profiles.json";
+const GAMES_DIR: &str = "games";
+const QUESTIONS_FILE: &str = "questions.csv";
+const ID_LENGTH: usize = 12;
+
+/// A player identity that outlives any single game
+#[derive(Clone, Deserialize, Serialize)]
+pub(crate) struct Profile {
+    /// The stable id of the profile
+    pub id: ProfileId,
+    /// The name the profile was created with
+    pub name: String,
+    /// The skill rating of the profile
+    #[serde(default = "initial_rating")]
+    pub rating: Rating,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct ProfileRecord {
+    #[serde(flatten)]
+    profile: Profile,
+    secret: Token,
+    #[serde(default)]
+    rating_history: Vec<RatingChange>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct NewProfileData {
+    /// The name to show for the profile
+    pub name: String,
+}
+
+/// Profiles and finished games. When a data directory is given everything is also written to
+/// disk and loaded again on start, otherwise it only lasts as long as the server.
+#[derive(Default)]
+pub(crate) struct Store {
+    dir: Option<PathBuf>,
+    profiles: RwLock<HashMap<ProfileId, ProfileRecord>>,
+    games: RwLock<Vec<GameExport>>,
+}
+
+impl Store {
+    pub(crate) fn open(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir.join(GAMES_DIR))?;
+        let profiles_path = dir.join(PROFILES_FILE);
+        let profiles = if profiles_path.exists() {
+            serde_json::from_slice(&fs::read(profiles_path)?)?
+        } else {
+            HashMap::new()
+        };
+        let mut games = Vec::new();
+        for entry in fs::read_dir(dir.join(GAMES_DIR))? {
+            let game: GameExport = serde_json::from_slice(&fs::read(entry?.path())?)?;
+            games.push(game);
+        }
+        // Keep games in the order they finished in
+        games.sort_by_key(|game| game.events.last().map_or(0, |recorded| recorded.at));
+        Ok(Self {
+            dir: Some(dir.to_path_buf()),
+            profiles: RwLock::new(profiles),
+            games: RwLock::new(games),
+        })
+    }
+
+    pub(crate) fn create_profile(&self, name: String) -> Result<ProfileCredentials> {
+        // The name is shown on every leaderboard, so it follows the rules for player names
+        let name = validation::player_name(&name)?;
+        let record = ProfileRecord {
+            profile: Profile {
+                id: generate_id(),
+                name,
+                rating: INITIAL_RATING,
+            },
+            secret: generate_token(),
+            rating_history: Vec::new(),
+        };
+        let credentials = ProfileCredentials {
+            id: record.profile.id.clone(),
+            secret: record.secret.clone(),
+        };
+        let mut profiles = self.profiles.write();
+        profiles.insert(record.profile.id.clone(), record);
+        self.persist(PROFILES_FILE, &*profiles)?;
+        Ok(credentials)
+    }
+
+    pub(crate) fn profile(&self, id: &str) -> Result<Profile> {
+        self.profiles
+            .read()
+            .get(id)
+            .map(|record| record.profile.clone())
+            .ok_or(Error::ProfileNotFound)
+    }
+
+    /// Confirm the credentials belong to a profile, returning its id
+    pub(crate) fn verify(&self, credentials: &ProfileCredentials) -> Result<ProfileId> {
+        let profiles = self.profiles.read();
+        let record = profiles
+            .get(&credentials.id)
+            .ok_or(Error::ProfileNotFound)?;
+        if record.secret != credentials.secret {
+            return Err(Error::InvalidToken);
+        }
+        Ok(record.profile.id.clone())
+    }
+
+    pub(crate) fn archive_game(&self, game: GameExport) -> Result<()> {
+        let file = Path::new(GAMES_DIR).join(format!("{}.json", generate_id()));
+        self.persist(&file, &game)?;
+        self.update_ratings(&game)?;
+        self.games.write().push(game);
+        Ok(())
+    }
+
+    /// Every rating the profile has had, oldest first
+    pub(crate) fn rating_history(&self, id: &str) -> Result<Vec<RatingChange>> {
+        self.profiles
+            .read()
+            .get(id)
+            .map(|record| record.rating_history.clone())
+            .ok_or(Error::ProfileNotFound)
+    }
+
+    fn update_ratings(&self, game: &GameExport) -> Result<()> {
+        let mut profiles = self.profiles.write();
+        let linked: HashSet<&ProfileId> = game.profiles.values().collect();
+        let placings: Vec<(&ProfileId, usize)> = linked
+            .into_iter()
+            .filter(|profile| profiles.contains_key(*profile))
+            .filter_map(|profile| {
+                let player = seat_of(game, profile)?;
+                placing(game, player).map(|place| (profile, place))
+            })
+            .collect();
+        let ratings: Vec<(Rating, usize)> = placings
+            .iter()
+            .map(|(profile, place)| (profiles[*profile].profile.rating, *place))
+            .collect();
+        let at = game.events.last().map_or(0, |recorded| recorded.at);
+        for ((profile, _), rating) in placings.iter().zip(rate(&ratings)) {
+            let record = profiles.get_mut(*profile).expect("to be a known profile");
+            record.profile.rating = rating;
+            record.rating_history.push(RatingChange {
+                game_id: game.game_id.clone(),
+                at,
+                rating,
+            });
+        }
+        self.persist(PROFILES_FILE, &*profiles)
+    }
+
+    /// Look at every finished game
+    pub(crate) fn games<T>(&self, view: impl FnOnce(&[GameExport]) -> T) -> T {
+        view(&self.games.read())
+    }
+
+    /// Rank the profiles that played in a season, with their names filled in
+    pub(crate) fn leaderboard(&self, season: &str, points: &SeasonPoints) -> Vec<Standing> {
+        let mut standings = self.games(|games| season_standings(season, games, points));
+        let profiles = self.profiles.read();
+        for standing in &mut standings {
+            if let Some(record) = profiles.get(&standing.profile) {
+                standing.name = record.profile.name.clone();
+            }
+        }
+        standings
+    }
+
+    /// The question bank kept in the data directory, if there is one
+    pub(crate) fn questions_file(&self) -> Option<PathBuf> {
+        let path = self.dir.as_ref()?.join(QUESTIONS_FILE);
+        path.exists().then_some(path)
+    }
+
+    /// Add questions to the question bank, skipping any already in it. Returns how many were
+    /// added.
+    pub(crate) fn import_questions(&self, questions: Vec<Question>) -> Result<usize> {
+        let Some(dir) = &self.dir else {
+            return Err(Error::StorageFailure);
+        };
+        let path = dir.join(QUESTIONS_FILE);
+        let mut lines = if path.exists() {
+            read_lines(&path).map_err(|e| {
+                eprintln!("Failed to read {path:?}, err: {e}");
+                Error::StorageFailure
+            })?
+        } else {
+            Vec::new()
+        };
+        let mut known: HashSet<String> = lines
+            .iter()
+            .filter_map(|line| parse_question(line).ok())
+            .map(|question| question.question.trim().to_lowercase())
+            .collect();
+        let before = lines.len();
+        for question in questions {
+            if known.insert(question.question.trim().to_lowercase()) {
+                lines.push(format_question(&question));
+            }
+        }
+        let added = lines.len() - before;
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+        fs::write(&path, contents).map_err(|e| {
+            eprintln!("Failed to write {path:?}, err: {e}");
+            Error::StorageFailure
+        })?;
+        Ok(added)
+    }
+
+    fn persist(&self, file: impl AsRef<Path>, value: &impl Serialize) -> Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let path = dir.join(file);
+        let json = serde_json::to_vec_pretty(value).expect("to stored value serialize");
+        fs::write(&path, json).map_err(|e| {
+            eprintln!("Failed to write {path:?}, err: {e}");
+            Error::StorageFailure
+        })
+    }
+}
+
+fn initial_rating() -> Rating {
+    INITIAL_RATING
+}
+
+fn generate_id() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(ID_LENGTH)
+        .map(char::from)
+        .collect()
+}
@@ -0,0 +1,159 @@
+use rand::{Rng, RngCore};
+
+use crate::{
+    bots::Difficulty,
+    types::{AnswerAmount, Game, Guesses, ScoreAmount, Scores},
+};
+
+/// What a player can see of the current round when deciding what to do, never the answer
+pub(crate) struct RoundView<'a> {
+    /// The question being asked
+    pub question: &'a str,
+    /// The guesses on the board so far
+    pub guesses: &'a Guesses,
+    /// The guess the player made this round, if any
+    pub own_guess: Option<AnswerAmount>,
+    /// The score of every participant going into the round
+    pub scores: Scores,
+    /// The most the player can wager
+    pub bankroll: ScoreAmount,
+    /// How many times the wager a correct wager pays out
+    pub payout_ratio: ScoreAmount,
+}
+
+impl<'a> RoundView<'a> {
+    pub(crate) fn new(game: &'a Game, player: &'a String) -> Self {
+        let round = game.current_round();
+        let scores = game.get_score();
+        let participant = game.participant(player);
+        let own_guess = round
+            .guesses
+            .iter()
+            .find(|g| g.participant() == participant)
+            .map(|g| g.guess);
+        Self {
+            question: &round.question.question,
+            guesses: &round.guesses,
+            own_guess,
+            bankroll: scores.get(participant).copied().unwrap_or(0),
+            scores,
+            payout_ratio: game.settings.scoring.payout_ratio,
+        }
+    }
+
+    /// Every distinct guess on the board, lowest first
+    pub(crate) fn board(&self) -> Vec<AnswerAmount> {
+        self.guesses.board().iter().map(|slot| slot.guess).collect()
+    }
+}
+
+/// A way of playing the game, used by bots and the simulator
+pub(crate) trait Strategy: Send + Sync {
+    /// The name to report results under
+    fn name(&self) -> String;
+
+    /// Come up with a guess for the round
+    fn guess(&self, view: &RoundView, rng: &mut dyn RngCore) -> AnswerAmount;
+
+    /// Pick a guess on the board to back, or none if the answer is below every guess, and how
+    /// much of the bankroll to put on it
+    fn wager(&self, view: &RoundView, rng: &mut dyn RngCore)
+        -> (Option<AnswerAmount>, ScoreAmount);
+}
+
+/// Plays without ever looking at the answer, sizing wagers by the odds and the scoreboard
+pub(crate) struct ScoreAware;
+
+impl Strategy for ScoreAware {
+    fn name(&self) -> String {
+        String::from("ScoreAware")
+    }
+
+    fn guess(&self, view: &RoundView, rng: &mut dyn RngCore) -> AnswerAmount {
+        // A number in the question is often in the neighbourhood of the answer
+        let hint = view
+            .question
+            .split(|c: char| !c.is_ascii_digit())
+            .find_map(|word| word.parse().ok());
+        let board = view.board();
+        match hint.or_else(|| board.get(board.len() / 2).copied()) {
+            Some(hint) => rng.gen_range(hint / 2..=hint.saturating_mul(2)),
+            None => rng.gen_range(1..=100),
+        }
+    }
+
+    fn wager(
+        &self,
+        view: &RoundView,
+        _rng: &mut dyn RngCore,
+    ) -> (Option<AnswerAmount>, ScoreAmount) {
+        let board = view.board();
+        let target = board.get(board.len() / 2).copied();
+        let leader = view.scores.values().max().copied().unwrap_or(0);
+        let bankroll = view.bankroll.max(0);
+        let amount = if bankroll >= leader {
+            // Protect a lead
+            0
+        } else if view.payout_ratio as usize > board.len() {
+            // A blind pick still pays off on average, so chase the leader
+            bankroll
+        } else {
+            bankroll / 2
+        };
+        (target, amount)
+    }
+}
+
+/// Someone to seat in a simulated game
+pub(crate) enum Contender {
+    /// A built-in bot, told the real answer to model how much a player of its difficulty knows
+    Bot(Difficulty),
+    /// A strategy that only sees what any player could
+    Strategy(Box<dyn Strategy>),
+}
+
+impl Contender {
+    /// The name to report results under
+    pub(crate) fn name(&self) -> String {
+        match self {
+            Self::Bot(difficulty) => format!("{difficulty:?}"),
+            Self::Strategy(strategy) => strategy.name(),
+        }
+    }
+
+    /// Come up with a guess for the round, only a bot is given the answer
+    pub(crate) fn guess(
+        &self,
+        view: &RoundView,
+        answer: AnswerAmount,
+        rng: &mut dyn RngCore,
+    ) -> AnswerAmount {
+        match self {
+            Self::Bot(difficulty) => difficulty.guess(view, answer, rng),
+            Self::Strategy(strategy) => strategy.guess(view, rng),
+        }
+    }
+
+    /// Pick a guess on the board to back and how much of the bankroll to put on it
+    pub(crate) fn wager(
+        &self,
+        view: &RoundView,
+        rng: &mut dyn RngCore,
+    ) -> (Option<AnswerAmount>, ScoreAmount) {
+        match self {
+            Self::Bot(difficulty) => difficulty.wager(view, rng),
+            Self::Strategy(strategy) => strategy.wager(view, rng),
+        }
+    }
+}
+
+/// Look up a built-in bot or strategy by the name it reports results under, ignoring case
+pub(crate) fn strategy_named(name: &str) -> Option<Contender> {
+    match name.to_lowercase().as_str() {
+        "easy" => Some(Contender::Bot(Difficulty::Easy)),
+        "medium" => Some(Contender::Bot(Difficulty::Medium)),
+        "hard" => Some(Contender::Bot(Difficulty::Hard)),
+        "scoreaware" => Some(Contender::Strategy(Box::new(ScoreAware))),
+        _ => None,
+    }
+}
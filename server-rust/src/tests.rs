@@ -1,6 +1,23 @@
-use crate::types::{Game, Round};
+use crate::bots::Difficulty;
+use crate::config::{apply_overrides, AppConfig, ConfigError, CorsConfig};
+use crate::export::{results_csv, GameExport};
+use crate::question_lookup::{format_question, parse_question, QuestionLookup};
+use crate::rating::{rate, INITIAL_RATING};
+use crate::simulator::{simulate, Simulation};
+use crate::stats::{player_stats, SeasonPoints};
+use crate::store::Store;
+use crate::strategy::{strategy_named, Contender, RoundView, Strategy};
+use crate::types::{
+    BadRequest, CreateGameData, DuplicateGuessPolicy, Error, Game, GameSettings, GameUpdateKind,
+    Games, GetQuestionLocation, Guess, Guesses, ProfileCredentials, Question, Round, RoundState,
+    ScoreAmount, ScoringRules, SessionData, TeamGuessPolicy, Visibility, Wager,
+};
+use rocket::{http::Status, tokio::sync::broadcast};
 use serde_json::from_str;
 use std::collections::HashMap;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
 
 #[test]
 fn test_get_closest_guess_multiple_guesses() {
@@ -207,10 +224,16 @@ fn test_get_score() {
                         "guess": 3,
                         "wager": 5
                     }
-                ]
+                ],
+                "phase": "Complete"
             }
         ],
-        "question_location": "File"
+        "question_location": "File",
+        "settings": {
+            "scoring": {
+                "reseed_score": 0
+            }
+        }
     }"#;
 
     let game: Game = from_str(game_json).expect("Failed to deserialize Game");
@@ -230,3 +253,1542 @@ fn test_get_score() {
 
     assert_eq!(scores, expected_scores);
 }
+
+fn spider_question() -> Question {
+    Question {
+        question: String::from("How many legs does a spider have?"),
+        answer: 8,
+        category: None,
+        min: None,
+        max: None,
+    }
+}
+
+fn new_game(players: &[&str]) -> Game {
+    let mut game = Game::default();
+    game.add_round_if_complete(spider_question());
+    for player in players {
+        game.add_player(player.to_string())
+            .expect("Failed to add player");
+    }
+    game
+}
+
+#[test]
+fn test_round_phase_follows_guesses_and_wagers() {
+    let mut game = new_game(&["Player1", "Player2"]);
+    assert_eq!(game.current_round().phase, RoundState::Start);
+
+    game.guess(Guess {
+        player: String::from("Player1"),
+        guess: 6,
+        team: None,
+    })
+    .expect("Failed to guess");
+    assert_eq!(game.current_round().phase, RoundState::CollectingGuesses);
+
+    game.guess(Guess {
+        player: String::from("Player2"),
+        guess: 10,
+        team: None,
+    })
+    .expect("Failed to guess");
+    assert_eq!(game.current_round().phase, RoundState::CollectingWagers);
+
+    for player in ["Player1", "Player2"] {
+        game.wager(Wager {
+            player: String::from(player),
+            guess: Some(6),
+            wager: 1,
+            team: None,
+            all_in: false,
+        })
+        .expect("Failed to wager");
+    }
+    assert_eq!(game.current_round().phase, RoundState::Complete);
+
+    let serialized = serde_json::to_value(&game).expect("Failed to serialize Game");
+    assert_eq!(serialized["rounds"][0]["phase"], "Complete");
+}
+
+#[test]
+fn test_player_joining_mid_round_waits_for_next_round() {
+    let mut game = new_game(&["Player1", "Player2"]);
+    game.guess(Guess {
+        player: String::from("Player1"),
+        guess: 6,
+        team: None,
+    })
+    .expect("Failed to guess");
+
+    game.add_player(String::from("Player3"))
+        .expect("Failed to add player");
+    assert!(game.pending_players.contains("Player3"));
+    assert!(matches!(
+        game.guess(Guess {
+            player: String::from("Player3"),
+            guess: 7,
+            team: None,
+        }),
+        Err(Error::PlayerWaitingForNextRound)
+    ));
+
+    game.guess(Guess {
+        player: String::from("Player2"),
+        guess: 10,
+        team: None,
+    })
+    .expect("Failed to guess");
+    for player in ["Player1", "Player2"] {
+        game.wager(Wager {
+            player: String::from(player),
+            guess: Some(6),
+            wager: 1,
+            team: None,
+            all_in: false,
+        })
+        .expect("Failed to wager");
+    }
+    game.add_round_if_complete(Question {
+        question: String::from("How many sides does a hexagon have?"),
+        answer: 6,
+        category: None,
+        min: None,
+        max: None,
+    });
+
+    assert!(game.players.contains("Player3"));
+    assert!(game.pending_players.is_empty());
+}
+
+#[test]
+fn test_player_joining_an_abandoned_round_restarts_it() {
+    let mut game = new_game(&["Player1", "Player2"]);
+    game.guess(Guess {
+        player: String::from("Player1"),
+        guess: 6,
+        team: None,
+    })
+    .expect("Failed to guess");
+    for player in ["Player1", "Player2"] {
+        game.remove_player(String::from(player))
+            .expect("Failed to remove player");
+    }
+    assert_eq!(game.current_round().phase, RoundState::CollectingGuesses);
+
+    game.add_player(String::from("Player3"))
+        .expect("Failed to add player");
+    assert!(game.players.contains("Player3"));
+    assert_eq!(game.current_round().phase, RoundState::Start);
+    assert!(game.current_round().guesses.is_empty());
+    game.guess(Guess {
+        player: String::from("Player3"),
+        guess: 7,
+        team: None,
+    })
+    .expect("Failed to guess");
+}
+
+#[test]
+fn test_player_leaving_mid_round_completes_round() {
+    let mut game = new_game(&["Player1", "Player2", "Player3"]);
+    for (player, guess) in [("Player1", 6), ("Player2", 10)] {
+        game.guess(Guess {
+            player: String::from(player),
+            guess,
+            team: None,
+        })
+        .expect("Failed to guess");
+    }
+    assert_eq!(game.current_round().phase, RoundState::CollectingGuesses);
+
+    // Player3 never guessed so leaving lets the others move on to wagers
+    game.remove_player(String::from("Player3"))
+        .expect("Failed to remove player");
+    assert_eq!(game.current_round().phase, RoundState::CollectingWagers);
+
+    game.wager(Wager {
+        player: String::from("Player1"),
+        guess: Some(6),
+        wager: 1,
+        team: None,
+        all_in: false,
+    })
+    .expect("Failed to wager");
+    game.remove_player(String::from("Player2"))
+        .expect("Failed to remove player");
+    assert_eq!(game.current_round().phase, RoundState::Complete);
+    assert_eq!(game.current_round().guesses.len(), 2);
+}
+
+#[test]
+fn test_spectators_do_not_hold_up_the_round() {
+    let mut game = new_game(&["Player1"]);
+    game.add_spectator(String::from("Television"))
+        .expect("Failed to add spectator");
+    assert!(matches!(
+        game.add_spectator(String::from("Television")),
+        Err(Error::SpectatorConflict)
+    ));
+
+    game.guess(Guess {
+        player: String::from("Player1"),
+        guess: 6,
+        team: None,
+    })
+    .expect("Failed to guess");
+    assert_eq!(game.current_round().phase, RoundState::CollectingWagers);
+
+    let view = game.spectator_view();
+    assert_eq!(view.board.len(), 1);
+    assert!(view.previous_round.is_none());
+    assert!(!view.scores.contains_key("Television"));
+}
+
+#[test]
+fn test_reconnect_requires_matching_token() {
+    let games = Games::default();
+    games
+        .insert(String::from("Game"), new_game(&[]))
+        .expect("Failed to insert game");
+    let session = games
+        .update("Game", |game| game.add_player(String::from("Player1")))
+        .expect("Failed to add player");
+    let connected = || {
+        games
+            .read("Game", |game| game.sessions["Player1"].connected)
+            .expect("Failed to read game")
+    };
+    assert!(!connected());
+
+    let wrong_session = SessionData {
+        player: String::from("Player1"),
+        token: String::from("not-the-token"),
+    };
+    assert!(matches!(
+        games.listen("Game", &wrong_session),
+        Err(Error::InvalidToken)
+    ));
+    assert!(matches!(
+        games.read("Game", |game| game.reconnect(&wrong_session)),
+        Ok(Err(Error::InvalidToken))
+    ));
+
+    // The player is connected for as long as any of their update streams is open
+    let first = games.listen("Game", &session).expect("Failed to listen");
+    let second = games.listen("Game", &session).expect("Failed to listen");
+    assert!(connected());
+    drop(first);
+    assert!(connected());
+    drop(second);
+    assert!(!connected());
+
+    // An open stream does not keep a deleted game alive
+    let listener = games.listen("Game", &session).expect("Failed to listen");
+    let mut updates = games.subscribe("Game").expect("Failed to subscribe");
+    let game = games
+        .read("Game", Game::clone)
+        .expect("Failed to read game");
+    games.delete("Game");
+    let update = updates.try_recv().expect("Failed to receive update");
+    assert_eq!(update.kind, GameUpdateKind::Deleted);
+    assert!(matches!(
+        updates.try_recv(),
+        Err(broadcast::error::TryRecvError::Closed)
+    ));
+    drop(listener);
+
+    // The token is never part of the shared game state
+    let serialized = serde_json::to_string(&game).expect("Failed to serialize Game");
+    assert!(!serialized.contains(&session.token));
+}
+
+#[test]
+fn test_captaincy_passes_to_the_longest_serving_member() {
+    let mut game = new_game(&["Player1", "Player2", "Player3", "Player4"]);
+    for player in ["Player1", "Player3", "Player4", "Player2"] {
+        game.join_team(String::from(player), String::from("Team1"))
+            .expect("Failed to join team");
+    }
+    assert_eq!(game.teams["Team1"].captain.as_deref(), Some("Player1"));
+
+    game.exit_team(&String::from("Player1"))
+        .expect("Failed to leave team");
+    assert_eq!(game.teams["Team1"].captain.as_deref(), Some("Player3"));
+    game.exit_team(&String::from("Player3"))
+        .expect("Failed to leave team");
+    assert_eq!(game.teams["Team1"].captain.as_deref(), Some("Player4"));
+}
+
+#[test]
+fn test_teams_share_a_guess_and_a_bankroll() {
+    let mut game = new_game(&["Player1", "Player2", "Player3"]);
+    game.settings.team_guess_policy = TeamGuessPolicy::Captain;
+    game.join_team(String::from("Player1"), String::from("Team1"))
+        .expect("Failed to join team");
+    game.join_team(String::from("Player2"), String::from("Team1"))
+        .expect("Failed to join team");
+    assert!(matches!(
+        game.join_team(String::from("Player3"), String::from("Player1")),
+        Err(Error::TeamConflict)
+    ));
+
+    // Only the captain, the first to join, can speak for the team
+    assert!(matches!(
+        game.guess(Guess {
+            player: String::from("Player2"),
+            guess: 6,
+            team: None,
+        }),
+        Err(Error::NotTeamCaptain)
+    ));
+    for (player, guess) in [("Player1", 8), ("Player3", 10)] {
+        game.guess(Guess {
+            player: String::from(player),
+            guess,
+            team: None,
+        })
+        .expect("Failed to guess");
+    }
+    assert_eq!(game.current_round().phase, RoundState::CollectingWagers);
+
+    // The team bankroll starts at 1 like any other participant
+    assert!(matches!(
+        game.wager(Wager {
+            player: String::from("Player1"),
+            guess: Some(8),
+            wager: 2,
+            team: None,
+            all_in: false,
+        }),
+        Err(Error::InvalidWager { max_wager: 1 })
+    ));
+    for player in ["Player1", "Player3"] {
+        game.wager(Wager {
+            player: String::from(player),
+            guess: Some(8),
+            wager: 1,
+            team: None,
+            all_in: false,
+        })
+        .expect("Failed to wager");
+    }
+    assert_eq!(game.current_round().phase, RoundState::Complete);
+
+    // 7 = 1*3 (correct wager) + 3 (closest guess) + 1 (everyone starts with 1)
+    // 4 = 1*3 (correct wager) + 1 (everyone starts with 1)
+    let scores = game.get_score();
+    let expected_scores: HashMap<String, i32> =
+        HashMap::from([(String::from("Team1"), 7), (String::from("Player3"), 4)]);
+    assert_eq!(scores, expected_scores);
+}
+
+#[test]
+fn test_generated_join_codes_and_lobby_listing() {
+    let question = spider_question();
+    let games = Games::default();
+    let public = games
+        .create_with_code(
+            String::from("Player1"),
+            question.clone(),
+            GetQuestionLocation::File,
+            GameSettings {
+                visibility: Visibility::Public,
+                ..Default::default()
+            },
+        )
+        .expect("Failed to create game");
+    let private = games
+        .create_with_code(
+            String::from("Player1"),
+            question,
+            GetQuestionLocation::File,
+            GameSettings::default(),
+        )
+        .expect("Failed to create game");
+
+    assert_ne!(public.game_id, private.game_id);
+    assert_eq!(public.game_id.len(), 6);
+    assert!(!public.game_id.contains(['0', 'O', '1', 'I', 'L']));
+
+    let lobbies = games.lobbies(|_| None);
+    assert_eq!(lobbies.len(), 1);
+    assert_eq!(lobbies[0].game_id, public.game_id);
+    assert_eq!(lobbies[0].players, 1);
+
+    // A join code can be typed in any case
+    let players = games
+        .read(&public.game_id.to_lowercase(), |game| game.players.len())
+        .expect("Failed to find game");
+    assert_eq!(players, 1);
+
+    let finish = |session: &SessionData| {
+        games.update(&public.game_id, |game| {
+            game.check_session(session)?;
+            game.finish();
+            Ok(())
+        })
+    };
+    let forged = SessionData {
+        player: public.session.player.clone(),
+        token: String::from("forged"),
+    };
+    assert!(matches!(finish(&forged), Err(Error::InvalidToken)));
+    assert_eq!(games.lobbies(|_| None).len(), 1);
+
+    finish(&public.session).expect("Failed to finish game");
+    assert!(games.lobbies(|_| None).is_empty());
+}
+
+#[test]
+fn test_idle_games_are_reaped() {
+    let games = Games::default();
+    games
+        .create(
+            String::from("Idle"),
+            String::from("Player1"),
+            spider_question(),
+            GetQuestionLocation::File,
+            GameSettings::default(),
+        )
+        .expect("Failed to create game");
+    let mut updates = games.subscribe("Idle").expect("Failed to subscribe");
+
+    let ttl = Duration::from_secs(60);
+    assert!(games.reap(Instant::now(), ttl).is_empty());
+    assert!(games.read("Idle", |_| ()).is_ok());
+
+    let later = Instant::now() + ttl * 2;
+    assert_eq!(games.reap(later, ttl), vec![String::from("Idle")]);
+    assert!(matches!(
+        games.read("Idle", |_| ()),
+        Err(Error::GameNotFound)
+    ));
+
+    let update = updates.try_recv().expect("Failed to receive final state");
+    assert_eq!(update.kind, GameUpdateKind::Expired);
+    assert!(update.game.players.contains("Player1"));
+}
+
+#[test]
+fn test_independent_games_do_not_block_each_other() {
+    const GAMES: usize = 8;
+    const GUESSES_PER_GAME: u32 = 200;
+
+    let games = Arc::new(Games::default());
+    for index in 0..=GAMES {
+        games
+            .create(
+                format!("Game{index}"),
+                String::from("Player1"),
+                spider_question(),
+                GetQuestionLocation::File,
+                GameSettings::default(),
+            )
+            .expect("Failed to create game");
+        games
+            .update(&format!("Game{index}"), |game| {
+                game.add_player(String::from("Player2"))
+            })
+            .expect("Failed to add player");
+    }
+
+    // Hold the lock on the first game the whole time the other games are being played
+    let (locked_sender, locked_receiver) = mpsc::channel();
+    let (release_sender, release_receiver) = mpsc::channel::<()>();
+    let slow_games = Arc::clone(&games);
+    let slow_request = thread::spawn(move || {
+        slow_games.update("Game0", |_| {
+            locked_sender.send(()).expect("Failed to signal lock");
+            release_receiver.recv().expect("Failed to wait for release");
+            Ok(())
+        })
+    });
+    locked_receiver.recv().expect("Failed to wait for lock");
+
+    let workers: Vec<_> = (1..=GAMES)
+        .map(|index| {
+            let games = Arc::clone(&games);
+            thread::spawn(move || {
+                for guess in 0..GUESSES_PER_GAME {
+                    games
+                        .update(&format!("Game{index}"), |game| {
+                            game.guess(Guess {
+                                player: String::from("Player1"),
+                                guess,
+                                team: None,
+                            })
+                        })
+                        .expect("Failed to guess");
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().expect("Worker panicked");
+    }
+    assert!(!slow_request.is_finished());
+
+    release_sender.send(()).expect("Failed to release lock");
+    slow_request
+        .join()
+        .expect("Slow request panicked")
+        .expect("Slow request failed");
+}
+
+#[test]
+fn test_replaying_events_rebuilds_the_game() {
+    let games = Games::default();
+    games
+        .create(
+            String::from("Replay"),
+            String::from("Player1"),
+            spider_question(),
+            GetQuestionLocation::File,
+            GameSettings::default(),
+        )
+        .expect("Failed to create game");
+    let game = games
+        .update("Replay", |game| {
+            game.add_player(String::from("Player2"))?;
+            for (player, guess) in [("Player1", 6), ("Player2", 10)] {
+                game.guess(Guess {
+                    player: String::from(player),
+                    guess,
+                    team: None,
+                })?;
+            }
+            game.add_player(String::from("Player3"))?;
+            game.remove_player(String::from("Player2"))?;
+            game.wager(Wager {
+                player: String::from("Player1"),
+                guess: Some(6),
+                wager: 1,
+                team: None,
+                all_in: false,
+            })?;
+            game.add_round_if_complete(Question {
+                question: String::from("How many sides does a hexagon have?"),
+                answer: 6,
+                category: None,
+                min: None,
+                max: None,
+            });
+            Ok(game.clone())
+        })
+        .expect("Failed to play game");
+
+    let replayed = Game::replay(game.events.clone());
+    assert_eq!(replayed.players, game.players);
+    assert_eq!(replayed.pending_players, game.pending_players);
+    assert_eq!(
+        serde_json::to_value(&replayed.rounds).expect("Failed to serialize rounds"),
+        serde_json::to_value(&game.rounds).expect("Failed to serialize rounds")
+    );
+    assert_eq!(replayed.get_score(), game.get_score());
+
+    // Exported events never include session tokens
+    let exported = serde_json::to_string(&game.events).expect("Failed to serialize events");
+    assert!(!exported.contains("token"));
+}
+
+#[test]
+fn test_export_and_import_round_trip() {
+    let games = Games::default();
+    games
+        .create(
+            String::from("Export"),
+            String::from("Player1"),
+            spider_question(),
+            GetQuestionLocation::File,
+            GameSettings::default(),
+        )
+        .expect("Failed to create game");
+    let export = games
+        .update("Export", |game| {
+            game.guess(Guess {
+                player: String::from("Player1"),
+                guess: 8,
+                team: None,
+            })?;
+            game.wager(Wager {
+                player: String::from("Player1"),
+                guess: Some(8),
+                wager: 1,
+                team: None,
+                all_in: false,
+            })?;
+            Ok(GameExport::new(String::from("Export"), game))
+        })
+        .expect("Failed to play game");
+    assert_eq!(export.rounds[0].score_changes["Player1"], 6);
+
+    let json = serde_json::to_string(&export).expect("Failed to serialize export");
+    let imported: GameExport = from_str(&json).expect("Failed to deserialize export");
+    let mut game = imported.into_game().expect("Failed to import game");
+    assert_eq!(game.get_score(), export.scores);
+
+    // The exported events carry no tokens so players need new ones to resume their seats
+    let sessions = game.reissue_tokens();
+    assert_eq!(sessions.len(), 1);
+    let stale_session = SessionData {
+        player: String::from("Player1"),
+        token: String::new(),
+    };
+    assert!(matches!(
+        game.reconnect(&stale_session),
+        Err(Error::InvalidToken)
+    ));
+    game.reconnect(&sessions[0]).expect("Failed to reconnect");
+
+    let mut future: GameExport = from_str(&json).expect("Failed to deserialize export");
+    future.version += 1;
+    assert!(matches!(
+        future.into_game(),
+        Err(Error::UnsupportedExportVersion)
+    ));
+
+    // Edited events are checked like the requests that caused them
+    let forged = |edit: &dyn Fn(&mut Vec<serde_json::Value>)| {
+        let mut export: serde_json::Value = from_str(&json).expect("Failed to deserialize export");
+        let events = export["events"]
+            .as_array_mut()
+            .expect("events to be a list");
+        edit(events);
+        let export: GameExport = serde_json::from_value(export).expect("Failed to read export");
+        export.into_game()
+    };
+    assert!(matches!(
+        forged(&|events| events.retain(|e| e["event"] == "PlayerJoined")),
+        Err(Error::InvalidGameLog { index: 0, .. })
+    ));
+    assert!(matches!(
+        forged(&|events| events[4]["wager"]["wager"] = serde_json::json!(1000)),
+        Err(Error::InvalidGameLog { index: 4, .. })
+    ));
+    assert!(matches!(
+        forged(&|events| events[3]["guess"]["player"] = serde_json::json!("Stranger")),
+        Err(Error::InvalidGameLog { index: 3, .. })
+    ));
+}
+
+#[test]
+fn test_results_csv() {
+    let round_json = r#"{
+        "question": {
+            "question": "How many legs does a spider have?",
+            "answer": 8
+        },
+        "guesses": [
+            {
+                "player": "Player1",
+                "guess": 6
+            },
+            {
+                "player": "Player2",
+                "guess": 10
+            }
+        ],
+        "wagers": [
+            {
+                "player": "Player1",
+                "guess": 6,
+                "wager": 1
+            },
+            {
+                "player": "Player2",
+                "guess": null,
+                "wager": 0
+            }
+        ],
+        "phase": "Complete"
+    }"#;
+    let round: Round = from_str(round_json).expect("Failed to deserialize Round");
+    let game = Game {
+        rounds: vec![round],
+        ..Default::default()
+    };
+
+    let expected = "\
+round,player,team,question,answer,guess,wager_target,wager_amount,score_change
+1,Player1,,How many legs does a spider have?,8,6,6,1,6
+1,Player2,,How many legs does a spider have?,8,10,,0,0
+";
+    assert_eq!(results_csv(&game), expected);
+
+    // A team member who left the submitting to a teammate still gets a row
+    let mut game = new_game(&["Player1", "Player2", "Player3"]);
+    for player in ["Player1", "Player2"] {
+        game.join_team(String::from(player), String::from("Team1"))
+            .expect("Failed to join team");
+    }
+    for (player, guess) in [("Player1", 6), ("Player3", 10)] {
+        game.guess(Guess {
+            player: String::from(player),
+            guess,
+            team: None,
+        })
+        .expect("Failed to guess");
+    }
+    for (player, guess, wager) in [("Player1", Some(6), 1), ("Player3", None, 0)] {
+        game.wager(Wager {
+            player: String::from(player),
+            guess,
+            wager,
+            team: None,
+            all_in: false,
+        })
+        .expect("Failed to wager");
+    }
+    let expected = "\
+round,player,team,question,answer,guess,wager_target,wager_amount,score_change
+1,Player1,Team1,How many legs does a spider have?,8,6,6,1,6
+1,Player2,Team1,How many legs does a spider have?,8,6,6,1,6
+1,Player3,,How many legs does a spider have?,8,10,,0,0
+";
+    assert_eq!(results_csv(&game), expected);
+}
+
+#[test]
+fn test_player_stats_from_finished_games() {
+    let store = Store::default();
+    let credentials = store
+        .create_profile(String::from("Alice"))
+        .expect("Failed to create profile");
+    let profile = store
+        .verify(&credentials)
+        .expect("Failed to verify profile");
+
+    let mut game = new_game(&["Player1", "Player2"]);
+    game.link_profile(String::from("Player1"), profile.clone())
+        .expect("Failed to link profile");
+    // Playing a second seat would count the game twice
+    assert!(matches!(
+        game.link_profile(String::from("Player2"), profile.clone()),
+        Err(Error::ProfileConflict)
+    ));
+    for (player, guess) in [("Player1", 6), ("Player2", 10)] {
+        game.guess(Guess {
+            player: String::from(player),
+            guess,
+            team: None,
+        })
+        .expect("Failed to guess");
+    }
+    for (player, guess) in [("Player1", 6), ("Player2", 10)] {
+        game.wager(Wager {
+            player: String::from(player),
+            guess: Some(guess),
+            wager: 1,
+            team: None,
+            all_in: false,
+        })
+        .expect("Failed to wager");
+    }
+    game.finish();
+    store
+        .archive_game(GameExport::new(String::from("Stats"), &game))
+        .expect("Failed to archive game");
+
+    let stats = store.games(|games| player_stats(&profile, games));
+    assert_eq!(stats.games_played, 1);
+    assert_eq!(stats.wins, 1);
+    assert_eq!(stats.average_guess_error, Some(25.0));
+    assert_eq!(stats.wager_accuracy, Some(1.0));
+    assert!(stats.biggest_payout > 0);
+
+    let wrong_secret = ProfileCredentials {
+        id: credentials.id,
+        secret: String::from("wrong"),
+    };
+    assert!(matches!(
+        store.verify(&wrong_secret),
+        Err(Error::InvalidToken)
+    ));
+}
+
+#[test]
+fn test_season_leaderboard() {
+    let store = Store::default();
+    let alice = store
+        .create_profile(String::from("Alice"))
+        .expect("Failed to create profile");
+    let bob = store
+        .create_profile(String::from("Bob"))
+        .expect("Failed to create profile");
+
+    for season in [Some(String::from("Spring")), None] {
+        let mut game = new_game(&["Player1", "Player2", "Player3"]);
+        game.settings.season = season;
+        for (player, profile) in [("Player1", &alice), ("Player2", &bob)] {
+            game.link_profile(String::from(player), profile.id.clone())
+                .expect("Failed to link profile");
+        }
+        for (player, guess) in [("Player1", 6), ("Player2", 10), ("Player3", 3)] {
+            game.guess(Guess {
+                player: String::from(player),
+                guess,
+                team: None,
+            })
+            .expect("Failed to guess");
+        }
+        for (player, guess) in [("Player1", 6), ("Player2", 10), ("Player3", 3)] {
+            game.wager(Wager {
+                player: String::from(player),
+                guess: Some(guess),
+                wager: 1,
+                team: None,
+                all_in: false,
+            })
+            .expect("Failed to wager");
+        }
+        game.finish();
+        store
+            .archive_game(GameExport::new(String::from("Season"), &game))
+            .expect("Failed to archive game");
+    }
+
+    // A game finished straight away by one person, or alongside only bots, counts for nothing
+    for bots in [0, 2] {
+        let mut game = new_game(&["Player1"]);
+        game.settings.season = Some(String::from("Spring"));
+        for bot in 0..bots {
+            game.add_bot(format!("Bot{bot}"), Difficulty::Easy)
+                .expect("Failed to add bot");
+        }
+        game.link_profile(String::from("Player1"), bob.id.clone())
+            .expect("Failed to link profile");
+        game.finish();
+        store
+            .archive_game(GameExport::new(String::from("Farmed"), &game))
+            .expect("Failed to archive game");
+    }
+
+    // A profile playing several seats, which older games allowed, is one person with one placing
+    for players in [
+        &["Player1", "Player2"][..],
+        &["Player1", "Player2", "Player3"],
+    ] {
+        let mut game = new_game(players);
+        game.settings.season = Some(String::from("Autumn"));
+        for (player, guess) in players.iter().zip([6, 10, 3]) {
+            game.guess(Guess {
+                player: player.to_string(),
+                guess,
+                team: None,
+            })
+            .expect("Failed to guess");
+        }
+        for (player, guess) in players.iter().zip([6, 10, 3]) {
+            game.wager(Wager {
+                player: player.to_string(),
+                guess: Some(guess),
+                wager: 1,
+                team: None,
+                all_in: false,
+            })
+            .expect("Failed to wager");
+        }
+        game.finish();
+        let mut export = GameExport::new(String::from("Shared"), &game);
+        for player in ["Player1", "Player2"] {
+            export.profiles.insert(String::from(player), bob.id.clone());
+        }
+        store.archive_game(export).expect("Failed to archive game");
+    }
+    let standings = store.leaderboard("Autumn", &SeasonPoints(vec![10, 6, 3]));
+    assert_eq!(standings.len(), 1);
+    assert_eq!((standings[0].points, standings[0].games_played), (10, 1));
+
+    // Only the game tagged with the season counts, and tied players share second place
+    let standings = store.leaderboard("Spring", &SeasonPoints(vec![10, 6, 3]));
+    let standings: Vec<_> = standings
+        .iter()
+        .map(|s| (s.name.as_str(), s.points, s.games_played))
+        .collect();
+    assert_eq!(standings, vec![("Alice", 10, 1), ("Bob", 6, 1)]);
+    assert!(store
+        .leaderboard("Summer", &SeasonPoints(vec![10, 6, 3]))
+        .is_empty());
+
+    // Both games move ratings, whatever their season
+    let alice_history = store
+        .rating_history(&alice.id)
+        .expect("Failed to get rating history");
+    assert_eq!(alice_history.len(), 2);
+    assert!(alice_history[1].rating > alice_history[0].rating);
+    let bob_rating = store
+        .profile(&bob.id)
+        .expect("Failed to get profile")
+        .rating;
+    assert!(bob_rating < INITIAL_RATING);
+}
+
+#[test]
+fn test_rate_multiplayer_game() {
+    let ratings = rate(&[(1500.0, 1), (1500.0, 2), (1500.0, 2)]);
+    assert_eq!(ratings[0], 1516.0);
+    assert_eq!(ratings[1], 1492.0);
+    assert_eq!(ratings[2], 1492.0);
+
+    // Beating a stronger player is worth more than beating a weaker one
+    let upset = rate(&[(1400.0, 1), (1600.0, 2)]);
+    let expected = rate(&[(1600.0, 1), (1400.0, 2)]);
+    assert!(upset[0] - 1400.0 > expected[0] - 1600.0);
+
+    assert_eq!(rate(&[(1500.0, 1)]), vec![1500.0]);
+}
+
+#[test]
+fn test_bots_play_along() {
+    let mut game = new_game(&["Player1"]);
+    for (bot, difficulty) in [("Bot1", Difficulty::Easy), ("Bot2", Difficulty::Hard)] {
+        game.add_bot(String::from(bot), difficulty)
+            .expect("Failed to add bot");
+    }
+
+    // Bots hold off until a person has guessed
+    game.play_bots(None);
+    assert_eq!(game.current_round().phase, RoundState::Start);
+
+    game.guess(Guess {
+        player: String::from("Player1"),
+        guess: 6,
+        team: None,
+    })
+    .expect("Failed to guess");
+    game.play_bots(None);
+    let round = game.current_round();
+    assert_eq!(round.phase, RoundState::CollectingWagers);
+    assert_eq!(round.guesses.len(), 3);
+    assert_eq!(round.wagers.len(), 2);
+
+    game.wager(Wager {
+        player: String::from("Player1"),
+        guess: Some(6),
+        wager: 1,
+        team: None,
+        all_in: false,
+    })
+    .expect("Failed to wager");
+    game.play_bots(Some(Question {
+        question: String::from("How many sides does a hexagon have?"),
+        answer: 6,
+        category: None,
+        min: None,
+        max: None,
+    }));
+    assert_eq!(game.rounds.len(), 2);
+    assert_eq!(game.current_round().phase, RoundState::Start);
+
+    // Once only bots are left they finish the round on their own
+    game.remove_player(String::from("Player1"))
+        .expect("Failed to remove player");
+    game.play_bots(None);
+    assert_eq!(game.current_round().phase, RoundState::Complete);
+
+    // Scores saturate rather than overflow, so a bot has to cope with the largest bankroll
+    let guesses = Guesses::default();
+    let view = RoundView {
+        question: "How many legs does a spider have?",
+        guesses: &guesses,
+        own_guess: None,
+        scores: HashMap::new(),
+        bankroll: ScoreAmount::MAX,
+        payout_ratio: 3,
+    };
+    for difficulty in [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard] {
+        let (_, wager) = difficulty.wager(&view, &mut rand::thread_rng());
+        assert!((0..=ScoreAmount::MAX).contains(&wager));
+    }
+}
+
+/// Always guesses the same and never wagers, to check the simulator seats custom strategies
+struct Cautious;
+
+impl Strategy for Cautious {
+    fn name(&self) -> String {
+        String::from("Cautious")
+    }
+
+    fn guess(&self, _view: &RoundView, _rng: &mut dyn rand::RngCore) -> u32 {
+        10
+    }
+
+    fn wager(&self, view: &RoundView, _rng: &mut dyn rand::RngCore) -> (Option<u32>, i32) {
+        (view.own_guess, 0)
+    }
+}
+
+#[test]
+fn test_simulate_strategies() {
+    let strategies = vec![
+        Contender::Strategy(Box::new(Cautious)),
+        strategy_named("easy").expect("Failed to find strategy"),
+        strategy_named("ScoreAware").expect("Failed to find strategy"),
+    ];
+    assert!(strategy_named("unknown").is_none());
+
+    let simulation = Simulation {
+        games: 20,
+        max_rounds: 3,
+        target_score: None,
+        scoring: ScoringRules::default(),
+    };
+    let report = simulate(&strategies, &QuestionLookup::default(), &simulation);
+    let names: Vec<&str> = report.results.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, vec!["Cautious", "Easy", "ScoreAware"]);
+    let total_wins: f64 = report.results.iter().map(|r| r.wins).sum();
+    assert!((total_wins - 20.0).abs() < 1e-9);
+    assert_eq!(report.rounds, 60);
+    // Never wagering, the only points come from having the closest guess
+    let cautious = &report.results[0];
+    assert!(cautious.percentile(0.0) >= 1);
+    assert!(cautious.percentile(0.0) <= cautious.percentile(0.5));
+    assert!(cautious.percentile(0.5) <= cautious.percentile(1.0));
+
+    // Everyone starts at the target so every game ends after one round, too soon for a comeback
+    let simulation = Simulation {
+        target_score: Some(5),
+        scoring: ScoringRules {
+            starting_score: 5,
+            ..Default::default()
+        },
+        ..simulation
+    };
+    let report = simulate(&strategies, &QuestionLookup::default(), &simulation);
+    assert_eq!(report.rounds, 20);
+    assert_eq!(report.comebacks, 0);
+}
+
+#[test]
+fn test_parse_question_lines() {
+    let question = parse_question("How many legs does a spider have?,8,animals")
+        .expect("Failed to parse question");
+    assert_eq!(question.answer, 8);
+    assert_eq!(question.category.as_deref(), Some("animals"));
+    assert_eq!(
+        format_question(&question),
+        "How many legs does a spider have?,8,animals"
+    );
+
+    for line in [
+        "How many legs does a spider have?",
+        "How many legs does a spider have?,eight",
+        ",8",
+        "How many legs, and arms, does a spider have?,8",
+    ] {
+        assert!(parse_question(line).is_err(), "{line} should not parse");
+    }
+}
+
+#[test]
+fn test_config_file_with_env_overrides() {
+    let mut table: toml::Table = r#"
+        [server]
+        port = 9000
+        season_points = [5, 1]
+
+        [questions]
+        source = "NumbersApi"
+
+        [game.scoring]
+        payout_ratio = 2
+    "#
+    .parse()
+    .expect("Failed to parse config");
+    apply_overrides(
+        &mut table,
+        [
+            ("BRAINS_AND_BETS_SERVER__PORT", "9001"),
+            ("BRAINS_AND_BETS_GAME__SCORING__STARTING_SCORE", "4"),
+            ("BRAINS_AND_BETS_GAME__SEASON", "2024"),
+            (
+                "BRAINS_AND_BETS_STORAGE__DATA_DIR",
+                "/var/lib/brains-and-bets",
+            ),
+            ("HOME", "/root"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string())),
+    );
+    let config: AppConfig = toml::Value::Table(table)
+        .try_into()
+        .expect("Failed to read config");
+    assert_eq!(config.server.port, 9001);
+    assert_eq!(config.server.season_points, vec![5, 1]);
+    assert_eq!(config.server.game_ttl, 21600);
+    assert!(matches!(
+        config.questions.source,
+        GetQuestionLocation::NumbersApi
+    ));
+    assert_eq!(
+        config.game.scoring,
+        ScoringRules {
+            payout_ratio: 2,
+            starting_score: 4,
+            ..Default::default()
+        }
+    );
+    assert_eq!(config.game.season.as_deref(), Some("2024"));
+    assert_eq!(
+        config.storage.data_dir.as_deref(),
+        Some(std::path::Path::new("/var/lib/brains-and-bets"))
+    );
+
+    // A host who picks some settings keeps the server's defaults for the rest
+    let overrides: CreateGameData = from_str(
+        r#"{"player": "Player1", "settings": {"visibility": "Public", "scoring": {"payout_ratio": 5}}}"#,
+    )
+    .expect("Failed to parse game data");
+    let mut settings = config.game.clone();
+    overrides.settings.apply(&mut settings);
+    assert_eq!(settings.visibility, Visibility::Public);
+    assert_eq!(
+        settings.scoring,
+        ScoringRules {
+            payout_ratio: 5,
+            starting_score: 4,
+            ..Default::default()
+        }
+    );
+
+    let mut table = toml::Table::new();
+    apply_overrides(
+        &mut table,
+        [("BRAINS_AND_BETS_SERVER__PROT".to_string(), "1".to_string())],
+    );
+    assert!(toml::Value::Table(table).try_into::<AppConfig>().is_err());
+    // A typo in the game settings is caught just the same
+    let table: toml::Table = "[game.scoring]\npayout_ration = 5"
+        .parse()
+        .expect("Failed to parse config");
+    assert!(toml::Value::Table(table).try_into::<AppConfig>().is_err());
+}
+
+#[test]
+fn test_cors_config() {
+    let cors = CorsConfig::default();
+    assert!(cors.allowed_methods.iter().any(|method| method == "DELETE"));
+    assert!(cors.to_cors().is_ok());
+
+    let cors = CorsConfig {
+        allow_credentials: true,
+        ..Default::default()
+    };
+    assert!(matches!(
+        cors.to_cors(),
+        Err(ConfigError::CredentialsWithAnyOrigin)
+    ));
+
+    let cors = CorsConfig {
+        allowed_origins: vec![String::from("https://brains-and-bets.example")],
+        allow_credentials: true,
+        ..Default::default()
+    };
+    assert!(cors.to_cors().is_ok());
+
+    let cors = CorsConfig {
+        allowed_methods: vec![String::from("delete"), String::from("FETCH")],
+        ..Default::default()
+    };
+    assert!(matches!(
+        cors.to_cors(),
+        Err(ConfigError::UnknownMethod(method)) if method == "FETCH"
+    ));
+}
+
+#[test]
+fn test_error_status_and_body() {
+    let mut game = new_game(&["Player1", "Player2"]);
+    let error = game
+        .wager(Wager {
+            player: String::from("Player1"),
+            guess: None,
+            wager: 1,
+            team: None,
+            all_in: false,
+        })
+        .expect_err("Wagered before guessing");
+    assert_eq!(error.status(), Status::Conflict);
+    let body = serde_json::to_value(BadRequest::new(error)).expect("Failed to serialize");
+    assert_eq!(body["error"], "RoundNotInCollectingWagersState");
+    assert_eq!(body["phase"], "Start");
+
+    for player in ["Player1", "Player2"] {
+        game.guess(Guess {
+            player: String::from(player),
+            guess: 8,
+            team: None,
+        })
+        .expect("Failed to guess");
+    }
+    let error = game
+        .wager(Wager {
+            player: String::from("Player1"),
+            guess: None,
+            wager: 5,
+            team: None,
+            all_in: false,
+        })
+        .expect_err("Wagered more than the bankroll");
+    assert_eq!(error.status(), Status::UnprocessableEntity);
+    let body = serde_json::to_value(BadRequest::new(error)).expect("Failed to serialize");
+    assert_eq!(body["error"], "InvalidWager");
+    assert_eq!(body["max_wager"], 1);
+    assert_eq!(body["message"], "invalid wager, at most 1 can be wagered");
+
+    assert_eq!(Error::GameNotFound.status(), Status::NotFound);
+    assert_eq!(Error::InvalidToken.status(), Status::Unauthorized);
+    assert_eq!(Error::NotTeamCaptain.status(), Status::Forbidden);
+    let body =
+        serde_json::to_value(BadRequest::new(Error::GameConflict)).expect("Failed to serialize");
+    assert_eq!(
+        body,
+        serde_json::json!({"error": "GameConflict", "message": "game conflict"})
+    );
+}
+
+#[test]
+fn test_player_name_and_game_id_validation() {
+    let mut game = new_game(&["Player1"]);
+    let session = game
+        .add_player(String::from("  Zoe\u{301}   Smith "))
+        .expect("Failed to add player");
+    assert_eq!(session.player, "Zo\u{e9} Smith");
+    for name in ["player1", "ZO\u{c9} SMITH"] {
+        assert!(matches!(
+            game.add_player(String::from(name)),
+            Err(Error::PlayerConflict)
+        ));
+    }
+    assert!(matches!(
+        game.add_player(String::from(" \t")),
+        Err(Error::PlayerNameInvalidCharacter { character: '\t' })
+    ));
+    assert!(matches!(
+        game.add_player(String::from("   ")),
+        Err(Error::PlayerNameEmpty)
+    ));
+    assert!(matches!(
+        game.add_player("x".repeat(33)),
+        Err(Error::PlayerNameTooLong { max_length: 32 })
+    ));
+    assert!(matches!(
+        game.add_player(String::from("Bell\u{7}")),
+        Err(Error::PlayerNameInvalidCharacter { character: '\u{7}' })
+    ));
+    let store = Store::default();
+    assert!(matches!(
+        store.create_profile("x".repeat(10_000)),
+        Err(Error::PlayerNameTooLong { max_length: 32 })
+    ));
+    let credentials = store
+        .create_profile(String::from("  Alice  "))
+        .expect("Failed to create profile");
+    let profile = store
+        .profile(&credentials.id)
+        .expect("Failed to get profile");
+    assert_eq!(profile.name, "Alice");
+
+    // Invisible characters would let a name pass for one already taken
+    for (name, character) in [
+        ("Player1\u{200B}", '\u{200B}'),
+        ("\u{202E}1reyalP", '\u{202E}'),
+    ] {
+        assert!(matches!(
+            game.add_player(String::from(name)),
+            Err(Error::PlayerNameInvalidCharacter { character: c }) if c == character
+        ));
+    }
+
+    let games = Games::default();
+    let create = |game_id: &str| {
+        games.create(
+            String::from(game_id),
+            String::from("Player1"),
+            spider_question(),
+            GetQuestionLocation::File,
+            GameSettings::default(),
+        )
+    };
+    assert!(create("friday-quiz_2").is_ok());
+    assert!(matches!(create(""), Err(Error::GameIdEmpty)));
+    assert!(matches!(
+        create("friday quiz"),
+        Err(Error::GameIdInvalidCharacter { character: ' ' })
+    ));
+    assert!(matches!(
+        create(&"Q".repeat(33)),
+        Err(Error::GameIdTooLong { max_length: 32 })
+    ));
+    assert!(matches!(
+        games.insert(String::from("../quiz"), new_game(&[])),
+        Err(Error::GameIdInvalidCharacter { character: '.' })
+    ));
+
+    // Team and spectator names follow the same rules as player names
+    assert!(matches!(
+        game.join_team(String::from("Player1"), String::from("PLAYER1")),
+        Err(Error::TeamConflict)
+    ));
+    assert!(matches!(
+        game.join_team(String::from("Player1"), String::from("Team\n1")),
+        Err(Error::PlayerNameInvalidCharacter { character: '\n' })
+    ));
+    game.join_team(String::from("Player1"), String::from(" Team  1 "))
+        .expect("Failed to join team");
+    game.join_team(session.player.clone(), String::from("TEAM 1"))
+        .expect("Failed to join team");
+    assert_eq!(game.teams.len(), 1);
+    assert_eq!(game.teams["Team 1"].members.len(), 2);
+
+    game.add_spectator(String::from(" Screen "))
+        .expect("Failed to add spectator");
+    assert!(matches!(
+        game.add_spectator(String::from("SCREEN")),
+        Err(Error::SpectatorConflict)
+    ));
+    assert!(matches!(
+        game.add_spectator(String::new()),
+        Err(Error::PlayerNameEmpty)
+    ));
+    game.remove_spectator(&String::from("screen"));
+    assert!(game.spectators.is_empty());
+}
+
+#[test]
+fn test_guess_range() {
+    let question = parse_question("How many planets are in our solar system?,8,space,1,20")
+        .expect("Failed to parse question");
+    assert_eq!((question.min, question.max), (Some(1), Some(20)));
+    assert_eq!(
+        format_question(&question),
+        "How many planets are in our solar system?,8,space,1,20"
+    );
+    let question =
+        parse_question("How many moons does Mars have?,2,,,10").expect("Failed to parse question");
+    assert_eq!(question.category, None);
+    assert_eq!((question.min, question.max), (None, Some(10)));
+    assert_eq!(
+        format_question(&question),
+        "How many moons does Mars have?,2,,,10"
+    );
+    assert!(parse_question("How many planets are in our solar system?,8,space,9,20").is_err());
+    assert!(parse_question("How many planets are in our solar system?,8,space,one").is_err());
+
+    let mut game = Game::default();
+    game.add_round_if_complete(
+        parse_question("How many planets are in our solar system?,8,space,1,20")
+            .expect("Failed to parse question"),
+    );
+    game.add_player(String::from("Player1"))
+        .expect("Failed to add player");
+    for guess in [0, 21, u32::MAX] {
+        assert!(matches!(
+            game.guess(Guess {
+                player: String::from("Player1"),
+                guess,
+                team: None,
+            }),
+            Err(Error::GuessOutOfRange { min: 1, max: 20 })
+        ));
+    }
+    game.guess(Guess {
+        player: String::from("Player1"),
+        guess: 20,
+        team: None,
+    })
+    .expect("Failed to guess");
+}
+
+#[test]
+fn test_duplicate_guesses() {
+    let mut game = new_game(&["Player1", "Player2", "Player3"]);
+    for (player, guess) in [("Player3", 10), ("Player2", 6), ("Player1", 6)] {
+        game.guess(Guess {
+            player: String::from(player),
+            guess,
+            team: None,
+        })
+        .expect("Failed to guess");
+    }
+    let board = &game.current_round().board;
+    assert_eq!(board.len(), 2);
+    assert_eq!(board[0].guess, 6);
+    assert_eq!(board[0].players, vec!["Player1", "Player2"]);
+    assert_eq!(board[1].guess, 10);
+    assert_eq!(board[1].players, vec!["Player3"]);
+
+    let settings = GameSettings {
+        duplicate_guesses: DuplicateGuessPolicy::Reject,
+        ..Default::default()
+    };
+    let mut game = Game::new(GetQuestionLocation::File, settings);
+    game.add_round_if_complete(
+        parse_question("How many legs does a spider have?,8,animals,8,9")
+            .expect("Failed to parse question"),
+    );
+    for player in ["Player1", "Player2"] {
+        game.add_player(String::from(player))
+            .expect("Failed to add player");
+    }
+    game.add_bot(String::from("Bot1"), Difficulty::Hard)
+        .expect("Failed to add bot");
+    for player in ["Player1", "Player1"] {
+        game.guess(Guess {
+            player: String::from(player),
+            guess: 8,
+            team: None,
+        })
+        .expect("Failed to guess");
+    }
+    assert!(matches!(
+        game.guess(Guess {
+            player: String::from("Player2"),
+            guess: 8,
+            team: None,
+        }),
+        Err(Error::GuessTaken { guess: 8 })
+    ));
+
+    // A bot steers clear of guesses that are already taken
+    game.play_bots(None);
+    let board = &game.current_round().board;
+    assert_eq!(board.len(), 2);
+    assert_eq!(board[1].guess, 9);
+    assert_eq!(board[1].players, vec!["Bot1"]);
+
+    // With every guess taken the last player shares a slot rather than holding up the round
+    game.guess(Guess {
+        player: String::from("Player2"),
+        guess: 8,
+        team: None,
+    })
+    .expect("Failed to guess");
+    assert_eq!(game.current_round().phase, RoundState::CollectingWagers);
+    let board = game.spectator_view().board;
+    assert_eq!(board[0].guess, 9);
+    assert_eq!(board[1].players, vec!["Player1", "Player2"]);
+}
+
+#[test]
+fn test_wager_rules_and_reseeding() {
+    let settings = GameSettings {
+        scoring: ScoringRules {
+            starting_score: 5,
+            minimum_wager: 2,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut game = Game::new(GetQuestionLocation::File, settings);
+    game.add_round_if_complete(spider_question());
+    for player in ["Player1", "Player2"] {
+        game.add_player(String::from(player))
+            .expect("Failed to add player");
+    }
+    for player in ["Player1", "Player2"] {
+        game.guess(Guess {
+            player: String::from(player),
+            guess: 10,
+            team: None,
+        })
+        .expect("Failed to guess");
+    }
+    let wager = |wager: ScoreAmount, all_in: bool| Wager {
+        player: String::from("Player1"),
+        guess: Some(10),
+        wager,
+        team: None,
+        all_in,
+    };
+    assert!(matches!(
+        game.wager(wager(-3, false)),
+        Err(Error::NegativeWager)
+    ));
+    assert!(matches!(
+        game.wager(wager(1, false)),
+        Err(Error::WagerBelowMinimum { min_wager: 2 })
+    ));
+    assert!(matches!(
+        game.wager(wager(6, false)),
+        Err(Error::InvalidWager { max_wager: 5 })
+    ));
+    game.wager(wager(0, true)).expect("Failed to go all in");
+    assert_eq!(game.current_round().wagers[0].wager, 5);
+
+    game.wager(Wager {
+        player: String::from("Player2"),
+        guess: None,
+        wager: 0,
+        team: None,
+        all_in: true,
+    })
+    .expect("Failed to go all in");
+    // Player1 lost all but 1 of their wager and is down to 1, below the minimum but still able
+    // to go all in
+    let scores = game.get_score();
+    assert_eq!(scores["Player1"], 1);
+    assert_eq!(game.settings.scoring.wager_range(scores["Player1"]), (1, 1));
+
+    // Anyone left broke after a round is topped back up
+    let mut game: Game = from_str(
+        r#"{
+        "players": ["Player1"],
+        "rounds": [
+            {
+                "question": {"question": "What is 2 + 2?", "answer": 4},
+                "guesses": [{"player": "Player1", "guess": 10}],
+                "wagers": [{"player": "Player1", "guess": 10, "wager": 5}],
+                "phase": "Complete"
+            }
+        ],
+        "question_location": "File"
+    }"#,
+    )
+    .expect("Failed to deserialize Game");
+    assert_eq!(game.get_score()["Player1"], 1);
+    game.settings.scoring.reseed_score = 0;
+    assert_eq!(game.get_score()["Player1"], -3);
+}
+
+#[test]
+fn test_scoring_rules_are_bounded() {
+    let games = Games::default();
+    let settings = GameSettings {
+        scoring: ScoringRules {
+            payout_ratio: i32::MAX,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert!(matches!(
+        games.create(
+            String::from("Greedy"),
+            String::from("Player1"),
+            spider_question(),
+            GetQuestionLocation::File,
+            settings,
+        ),
+        Err(Error::InvalidScoringRule { max: 100, .. })
+    ));
+
+    // Scores stop at the limit rather than overflowing
+    let mut game: Game = from_str(
+        r#"{
+        "players": ["Player1"],
+        "rounds": [
+            {
+                "question": {"question": "What is 2 + 2?", "answer": 4},
+                "guesses": [{"player": "Player1", "guess": 4}],
+                "wagers": [{"player": "Player1", "guess": 4, "wager": 2000000000}],
+                "phase": "Complete"
+            }
+        ],
+        "question_location": "File"
+    }"#,
+    )
+    .expect("Failed to deserialize Game");
+    game.settings.scoring.payout_ratio = 100;
+    assert_eq!(game.get_score()["Player1"], i32::MAX);
+}
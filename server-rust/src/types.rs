@@ -1,25 +1,38 @@
 use derive_more::{Deref, IntoIterator};
 use displaydoc::Display;
+use parking_lot::{Mutex, RwLock};
+use rand::{distributions::Alphanumeric, Rng};
 use rocket::{
     http::{ContentType, Status},
     response::{self, Responder},
+    tokio::sync::broadcast,
     Request, Response,
 };
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{hash_map::Entry, HashMap, HashSet},
     io::Cursor,
+    sync::{Arc, Weak},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use thiserror::Error;
 
+use crate::{bots::Difficulty, config, rating::Rating, strategy::RoundView, validation};
+
 pub(crate) type Result<T> = std::result::Result<T, Error>;
 pub(crate) type Player = String;
+pub(crate) type Spectator = String;
+pub(crate) type Token = String;
+pub(crate) type TeamName = String;
+pub(crate) type ProfileId = String;
+pub(crate) type SeasonName = String;
 pub(crate) type AnswerAmount = u32;
 pub(crate) type ScoreAmount = i32;
 pub(crate) type GameId = String;
 pub(crate) type Scores = HashMap<Player, ScoreAmount>;
 
 #[derive(Deserialize, Serialize, Debug, Display, Error)]
+#[serde(tag = "error")]
 pub(crate) enum Error {
     /// game conflict
     GameConflict,
@@ -29,26 +42,119 @@ pub(crate) enum Error {
     PlayerConflict,
     /// player not found
     PlayerNotFound,
-    /// round not in start state
-    RoundNotInStartState,
-    /// round not in collecting guesses state
-    RoundNotInCollectingGuessesState,
-    /// round not in collecting wagers state
-    RoundNotInCollectingWagersState,
+    /// player waiting for next round
+    PlayerWaitingForNextRound,
+    /// spectator conflict
+    SpectatorConflict,
+    /// invalid token
+    InvalidToken,
+    /// unsupported export version
+    UnsupportedExportVersion,
+    /// game finished
+    GameFinished,
+    /// profile not found
+    ProfileNotFound,
+    /// storage failure
+    StorageFailure,
+    /// round not in start state, it is in {phase:?}
+    RoundNotInStartState { phase: RoundState },
+    /// team conflict
+    TeamConflict,
+    /// not team captain
+    NotTeamCaptain,
+    /// round not in collecting guesses state, it is in {phase:?}
+    RoundNotInCollectingGuessesState { phase: RoundState },
+    /// round not in collecting wagers state, it is in {phase:?}
+    RoundNotInCollectingWagersState { phase: RoundState },
     /// guess not found
     GuessNotFound,
-    /// invalid wager
-    InvalidWager,
+    /// invalid wager, at most {max_wager} can be wagered
+    InvalidWager { max_wager: ScoreAmount },
+    /// wager cannot be negative
+    NegativeWager,
+    /// wager is below the minimum of {min_wager}
+    WagerBelowMinimum { min_wager: ScoreAmount },
+    /// player name is empty
+    PlayerNameEmpty,
+    /// player name is longer than {max_length} characters
+    PlayerNameTooLong { max_length: usize },
+    /// player name contains the character {character:?}, which is not allowed
+    PlayerNameInvalidCharacter { character: char },
+    /// game id is empty
+    GameIdEmpty,
+    /// game id is longer than {max_length} characters
+    GameIdTooLong { max_length: usize },
+    /// game id contains the character {character:?}, only letters, digits, - and _ are allowed
+    GameIdInvalidCharacter { character: char },
+    /// {rule} must be between {min} and {max}
+    InvalidScoringRule {
+        rule: String,
+        min: ScoreAmount,
+        max: ScoreAmount,
+    },
+    /// event {index} of the game log is invalid: {reason}
+    InvalidGameLog { index: usize, reason: String },
+    /// event not expected at this point in the game
+    UnexpectedEvent,
+    /// guess must be between {min} and {max}
+    GuessOutOfRange {
+        min: AnswerAmount,
+        max: AnswerAmount,
+    },
+    /// someone has already guessed {guess}
+    GuessTaken { guess: AnswerAmount },
+    /// profile already linked to another player in the game
+    ProfileConflict,
+}
+
+impl Error {
+    /// The HTTP status the error is reported with
+    pub(crate) fn status(&self) -> Status {
+        match self {
+            Self::GameNotFound | Self::PlayerNotFound | Self::ProfileNotFound => Status::NotFound,
+            Self::GameConflict
+            | Self::PlayerConflict
+            | Self::SpectatorConflict
+            | Self::TeamConflict
+            | Self::ProfileConflict
+            | Self::PlayerWaitingForNextRound
+            | Self::GameFinished
+            | Self::GuessTaken { .. }
+            | Self::RoundNotInStartState { .. }
+            | Self::RoundNotInCollectingGuessesState { .. }
+            | Self::RoundNotInCollectingWagersState { .. } => Status::Conflict,
+            Self::GuessNotFound
+            | Self::GuessOutOfRange { .. }
+            | Self::InvalidWager { .. }
+            | Self::NegativeWager
+            | Self::WagerBelowMinimum { .. }
+            | Self::UnsupportedExportVersion
+            | Self::InvalidGameLog { .. }
+            | Self::InvalidScoringRule { .. }
+            | Self::UnexpectedEvent
+            | Self::PlayerNameEmpty
+            | Self::PlayerNameTooLong { .. }
+            | Self::PlayerNameInvalidCharacter { .. }
+            | Self::GameIdEmpty
+            | Self::GameIdTooLong { .. }
+            | Self::GameIdInvalidCharacter { .. } => Status::UnprocessableEntity,
+            Self::InvalidToken => Status::Unauthorized,
+            Self::NotTeamCaptain => Status::Forbidden,
+            Self::StorageFailure => Status::InternalServerError,
+        }
+    }
 }
 
+/// The body of every error response, the error's context fields sit alongside its name
 #[derive(Deserialize, Serialize)]
 pub(crate) struct BadRequest {
-    error: Error,
-    message: String,
+    #[serde(flatten)]
+    pub error: Error,
+    pub message: String,
 }
 
 impl BadRequest {
-    fn new(error: Error) -> Self {
+    pub(crate) fn new(error: Error) -> Self {
         Self {
             message: format!("{error}"),
             error,
@@ -58,10 +164,11 @@ impl BadRequest {
 
 impl<'r> Responder<'r, 'static> for Error {
     fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let status = self.status();
         let body = BadRequest::new(self);
         let body = serde_json::to_string(&body).expect("to BadRequest serialize");
         Ok(Response::build()
-            .status(Status::BadRequest)
+            .status(status)
             .header(ContentType::JSON)
             .sized_body(body.len(), Cursor::new(body))
             .finalize())
@@ -72,14 +179,77 @@ impl<'r> Responder<'r, 'static> for Error {
 pub(crate) struct PlayerData {
     /// The player with which the request is associated
     pub player: Player,
+    /// The persistent profile the player's results count towards
+    #[serde(default)]
+    pub profile: Option<ProfileCredentials>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct ProfileCredentials {
+    /// The id of the profile
+    pub id: ProfileId,
+    /// The secret handed out when the profile was created
+    pub secret: Token,
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct SessionData {
+    /// The player with which the request is associated
+    pub player: Player,
+    /// The secret the player presents to resume their seat
+    pub token: Token,
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct NewGameData {
+    /// The join code generated for the game
+    pub game_id: GameId,
+    /// The session of the player who created the game
+    #[serde(flatten)]
+    pub session: SessionData,
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct LobbyData {
+    /// The id players use to join the game
+    pub game_id: GameId,
+    /// The number of players in the game, including those waiting for the next round
+    pub players: usize,
+    /// The number of rounds started so far
+    pub rounds: usize,
+    /// The phase the current round is in
+    pub phase: RoundState,
+    /// The skill rating of each player with a profile
+    pub ratings: HashMap<Player, Rating>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct TeamData {
+    /// The player with which the request is associated
+    pub player: Player,
+    /// The team the player wants to join
+    pub team: TeamName,
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct SpectatorData {
+    /// The spectator with which the request is associated
+    pub spectator: Spectator,
 }
 
 #[derive(Deserialize, Serialize)]
 pub(crate) struct CreateGameData {
     /// The player with which the request is associated
     pub player: Player,
-    /// The location to get questions from
-    pub get_questions_from: GetQuestionLocation,
+    /// The persistent profile the player's results count towards
+    #[serde(default)]
+    pub profile: Option<ProfileCredentials>,
+    /// The location to get questions from, the server's default source if not given
+    #[serde(default)]
+    pub get_questions_from: Option<GetQuestionLocation>,
+    /// The rules the game is played with, any left out are the server's default rules
+    #[serde(default)]
+    pub settings: GameSettingsOverrides,
 }
 
 #[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
@@ -88,6 +258,31 @@ pub(crate) struct Question {
     pub question: String,
     /// The correct answer to the question
     pub answer: AnswerAmount,
+    /// The kind of question, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// The lowest guess allowed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<AnswerAmount>,
+    /// The highest guess allowed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<AnswerAmount>,
+}
+
+impl Question {
+    /// The lowest and highest guesses allowed
+    pub(crate) fn guess_range(&self) -> (AnswerAmount, AnswerAmount) {
+        (
+            self.min.unwrap_or(AnswerAmount::MIN),
+            self.max.unwrap_or(AnswerAmount::MAX),
+        )
+    }
+
+    /// Move a guess into the allowed range
+    pub(crate) fn clamp_guess(&self, guess: AnswerAmount) -> AnswerAmount {
+        let (min, max) = self.guess_range();
+        guess.clamp(min, max)
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
@@ -96,6 +291,16 @@ pub(crate) struct Guess {
     pub player: Player,
     /// The players guess for the round
     pub guess: AnswerAmount,
+    /// The team the guess is made on behalf of, filled in by the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team: Option<TeamName>,
+}
+
+impl Guess {
+    /// The team or solo player the guess counts for
+    pub fn participant(&self) -> &str {
+        self.team.as_deref().unwrap_or(&self.player)
+    }
 }
 
 #[derive(Debug, Default, Clone, Deserialize, Serialize, Deref, IntoIterator)]
@@ -103,16 +308,58 @@ pub(crate) struct Guesses(Vec<Guess>);
 
 impl Guesses {
     pub fn add_or_replace(&mut self, guess: Guess) {
-        if let Some(existing_guess_index) = self.iter().position(|g| g.player == guess.player) {
+        if let Some(existing_guess_index) = self
+            .iter()
+            .position(|g| g.participant() == guess.participant())
+        {
             self.0[existing_guess_index] = guess;
         } else {
             self.0.push(guess);
         }
     }
 
-    fn contains(&mut self, guess: AnswerAmount) -> bool {
+    pub fn contains(&self, guess: AnswerAmount) -> bool {
         self.iter().any(|g| g.guess == guess)
     }
+
+    /// If someone other than the participant has made the guess
+    fn taken_by_other(&self, guess: AnswerAmount, participant: &str) -> bool {
+        self.iter()
+            .any(|g| g.guess == guess && g.participant() != participant)
+    }
+
+    fn remove(&mut self, participant: &str) {
+        self.0.retain(|g| g.participant() != participant);
+    }
+
+    /// Every distinct guess, lowest first, with the participants who made it
+    pub fn board(&self) -> Vec<BoardSlot> {
+        let mut board: Vec<BoardSlot> = Vec::new();
+        for guess in self.iter() {
+            let participant = guess.participant().to_string();
+            match board.iter_mut().find(|slot| slot.guess == guess.guess) {
+                Some(slot) => slot.players.push(participant),
+                None => board.push(BoardSlot {
+                    guess: guess.guess,
+                    players: vec![participant],
+                }),
+            }
+        }
+        board.sort_unstable_by_key(|slot| slot.guess);
+        for slot in &mut board {
+            slot.players.sort_unstable();
+        }
+        board
+    }
+}
+
+/// A single number on the board, guesses that are the same share a slot
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub(crate) struct BoardSlot {
+    /// The guess
+    pub guess: AnswerAmount,
+    /// The participants that made the guess, a team shows up under its name
+    pub players: Vec<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
@@ -123,6 +370,19 @@ pub(crate) struct Wager {
     pub guess: Option<AnswerAmount>,
     /// The players wager amount
     pub wager: ScoreAmount,
+    /// The team the wager is made on behalf of, filled in by the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team: Option<TeamName>,
+    /// Wager the whole bankroll, the server fills in the amount
+    #[serde(default)]
+    pub all_in: bool,
+}
+
+impl Wager {
+    /// The team or solo player the wager counts for
+    pub fn participant(&self) -> &str {
+        self.team.as_deref().unwrap_or(&self.player)
+    }
 }
 
 #[derive(Debug, Default, Clone, Deserialize, Serialize, Deref, IntoIterator)]
@@ -130,16 +390,24 @@ pub(crate) struct Wagers(Vec<Wager>);
 
 impl Wagers {
     pub fn add_or_replace(&mut self, wager: Wager) {
-        if let Some(existing_wager_index) = self.iter().position(|w| w.player == wager.player) {
+        if let Some(existing_wager_index) = self
+            .iter()
+            .position(|w| w.participant() == wager.participant())
+        {
             self.0[existing_wager_index] = wager;
         } else {
             self.0.push(wager);
         }
     }
+
+    fn remove(&mut self, participant: &str) {
+        self.0.retain(|w| w.participant() != participant);
+    }
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) enum RoundState {
+    #[default]
     Start,
     CollectingGuesses,
     CollectingWagers,
@@ -154,6 +422,12 @@ pub(crate) struct Round {
     pub guesses: Guesses,
     /// The list of wagers made, one per player
     pub wagers: Wagers,
+    /// The phase the round is in, advanced as guesses and wagers come in
+    #[serde(default)]
+    pub phase: RoundState,
+    /// The distinct guesses to wager on, kept in step with the guesses
+    #[serde(default)]
+    pub board: Vec<BoardSlot>,
 }
 
 impl Round {
@@ -162,19 +436,39 @@ impl Round {
             question,
             guesses: Guesses::default(),
             wagers: Wagers::default(),
+            phase: RoundState::Start,
+            board: Vec::new(),
         }
     }
 
-    fn state(&self, players: usize) -> RoundState {
-        match (self.guesses.len(), self.wagers.len()) {
-            (0, 0) => RoundState::Start,
-            (guesses, 0) if guesses < players => RoundState::CollectingGuesses,
-            (guesses, wagers) if guesses == players && wagers < players => {
+    fn add_or_replace_guess(&mut self, guess: Guess) {
+        self.guesses.add_or_replace(guess);
+        self.board = self.guesses.board();
+    }
+
+    fn remove_guess(&mut self, participant: &str) {
+        self.guesses.remove(participant);
+        self.board = self.guesses.board();
+    }
+
+    /// Move the round forward if every participant has done what the current phase asks of them
+    fn advance(&mut self, participants: &HashSet<String>) {
+        let everyone_guessed = !participants.is_empty()
+            && participants
+                .iter()
+                .all(|p| self.guesses.iter().any(|g| g.participant() == p));
+        let everyone_wagered = !participants.is_empty()
+            && participants
+                .iter()
+                .all(|p| self.wagers.iter().any(|w| w.participant() == p));
+        self.phase = match self.phase {
+            RoundState::Start | RoundState::CollectingGuesses if everyone_guessed => {
                 RoundState::CollectingWagers
             }
-            (guesses, wagers) if guesses == players && wagers == players => RoundState::Complete,
-            _ => panic!("Round in unknown state"),
-        }
+            RoundState::Start if !self.guesses.is_empty() => RoundState::CollectingGuesses,
+            RoundState::CollectingWagers if everyone_wagered => RoundState::Complete,
+            phase => phase,
+        };
     }
 
     pub fn get_closest_guess(&self) -> Option<u32> {
@@ -192,23 +486,25 @@ impl Round {
         for wager in self.wagers.iter() {
             let score_change = if wager.guess == closest_guess {
                 // With the correct wager, the player gets a payout proportional to the wager amount
-                wager.wager * payout_ratio
+                wager.wager.saturating_mul(payout_ratio)
             } else if wager.wager >= 1 {
                 // With an incorrect wager of at least 1, the player loses all but 1 of the wager amount
-                -wager.wager + 1
+                1 - wager.wager
             } else {
                 // With a wager of 0, there is no gain or loss
                 0
             };
-            score_changes.insert(wager.player.clone(), score_change);
+            score_changes.insert(wager.participant().to_string(), score_change);
         }
         // Add an extra bonus to the players with the closest guess
         if let Some(closest_guess) = closest_guess {
             for guess in self.guesses.iter() {
                 if guess.guess == closest_guess {
-                    let closest_player_score =
-                        score_changes.entry(guess.player.clone()).or_insert(0);
-                    *closest_player_score += closest_guess_bonus;
+                    let closest_player_score = score_changes
+                        .entry(guess.participant().to_string())
+                        .or_insert(0);
+                    *closest_player_score =
+                        closest_player_score.saturating_add(closest_guess_bonus);
                 }
             }
         }
@@ -216,10 +512,234 @@ impl Round {
     }
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Session {
+    /// The secret handed to the player when they joined, never shared with other players
+    #[serde(skip_serializing, default)]
+    token: Token,
+    /// If the player has a client listening for updates, bots are always connected
+    pub connected: bool,
+}
+
+pub(crate) fn generate_token() -> Token {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+const TOKEN_LENGTH: usize = 24;
+
+#[derive(Default, Clone, Copy, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub(crate) enum TeamGuessPolicy {
+    /// Any member can submit, the last guess or wager submitted counts for the team
+    #[default]
+    LastSubmitted,
+    /// Only the team captain can submit guesses and wagers
+    Captain,
+}
+
+#[derive(Default, Clone, Copy, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub(crate) enum DuplicateGuessPolicy {
+    /// Participants can make the same guess, they share a slot on the board
+    #[default]
+    Merge,
+    /// A guess someone else has already made is turned away
+    Reject,
+}
+
+#[derive(Default, Clone, Copy, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub(crate) enum Visibility {
+    /// Only players who know the game id can find the game
+    #[default]
+    Private,
+    /// The game is listed in the open lobbies
+    Public,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GameSettings {
+    /// How a team settles on its single guess and wager
+    #[serde(default)]
+    pub team_guess_policy: TeamGuessPolicy,
+    /// If the game shows up in the list of open lobbies
+    #[serde(default)]
+    pub visibility: Visibility,
+    /// The season the game counts towards once finished
+    #[serde(
+        default,
+        deserialize_with = "config::optional_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub season: Option<SeasonName>,
+    /// How rounds are scored
+    #[serde(default)]
+    pub scoring: ScoringRules,
+    /// What happens when a participant makes a guess someone else already has
+    #[serde(default)]
+    pub duplicate_guesses: DuplicateGuessPolicy,
+}
+
+/// The settings a host picked for their game, anything they leave out keeps the server's default
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct GameSettingsOverrides {
+    pub team_guess_policy: Option<TeamGuessPolicy>,
+    pub visibility: Option<Visibility>,
+    pub season: Option<SeasonName>,
+    pub scoring: ScoringRulesOverrides,
+    pub duplicate_guesses: Option<DuplicateGuessPolicy>,
+}
+
+impl GameSettingsOverrides {
+    /// Override the default settings with any the host picked
+    pub(crate) fn apply(self, settings: &mut GameSettings) {
+        settings.team_guess_policy = self.team_guess_policy.unwrap_or(settings.team_guess_policy);
+        settings.visibility = self.visibility.unwrap_or(settings.visibility);
+        if let Some(season) = self.season {
+            settings.season = Some(season);
+        }
+        self.scoring.apply(&mut settings.scoring);
+        settings.duplicate_guesses = self.duplicate_guesses.unwrap_or(settings.duplicate_guesses);
+    }
+}
+
+/// The scoring rules a host picked for their game, anything they leave out keeps the server's
+/// default
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ScoringRulesOverrides {
+    pub payout_ratio: Option<ScoreAmount>,
+    pub closest_guess_bonus: Option<ScoreAmount>,
+    pub starting_score: Option<ScoreAmount>,
+    pub minimum_wager: Option<ScoreAmount>,
+    pub reseed_score: Option<ScoreAmount>,
+}
+
+impl ScoringRulesOverrides {
+    fn apply(self, rules: &mut ScoringRules) {
+        rules.payout_ratio = self.payout_ratio.unwrap_or(rules.payout_ratio);
+        rules.closest_guess_bonus = self
+            .closest_guess_bonus
+            .unwrap_or(rules.closest_guess_bonus);
+        rules.starting_score = self.starting_score.unwrap_or(rules.starting_score);
+        rules.minimum_wager = self.minimum_wager.unwrap_or(rules.minimum_wager);
+        rules.reseed_score = self.reseed_score.unwrap_or(rules.reseed_score);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ScoringRules {
+    /// How many times the wager a correct wager pays out
+    pub payout_ratio: ScoreAmount,
+    /// The extra points for having the closest guess
+    pub closest_guess_bonus: ScoreAmount,
+    /// The score everyone starts the game with
+    pub starting_score: ScoreAmount,
+    /// The smallest wager allowed, a participant with less can still go all in
+    pub minimum_wager: ScoreAmount,
+    /// The score a participant who has gone broke is topped back up to after a round, 0 leaves
+    /// them broke
+    pub reseed_score: ScoreAmount,
+}
+
+impl Default for ScoringRules {
+    fn default() -> Self {
+        Self {
+            payout_ratio: 3,
+            closest_guess_bonus: 3,
+            starting_score: 1,
+            minimum_wager: 0,
+            reseed_score: 1,
+        }
+    }
+}
+
+/// The largest payout ratio a game can use
+const MAX_PAYOUT_RATIO: ScoreAmount = 100;
+/// The largest value any of the other scoring rules can be set to
+const MAX_SCORING_RULE: ScoreAmount = 1_000_000;
+
+impl ScoringRules {
+    /// Check every rule is in a range that keeps scores sensible
+    pub(crate) fn validate(&self) -> Result<()> {
+        for (rule, value, max) in [
+            ("payout_ratio", self.payout_ratio, MAX_PAYOUT_RATIO),
+            (
+                "closest_guess_bonus",
+                self.closest_guess_bonus,
+                MAX_SCORING_RULE,
+            ),
+            ("starting_score", self.starting_score, MAX_SCORING_RULE),
+            ("minimum_wager", self.minimum_wager, MAX_SCORING_RULE),
+            ("reseed_score", self.reseed_score, MAX_SCORING_RULE),
+        ] {
+            if !(0..=max).contains(&value) {
+                return Err(Error::InvalidScoringRule {
+                    rule: rule.to_string(),
+                    min: 0,
+                    max,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// How each score changed in the round
+    pub(crate) fn score_changes(&self, round: &Round) -> Scores {
+        round.get_score_changes(self.payout_ratio, self.closest_guess_bonus)
+    }
+
+    /// The smallest and largest wager allowed with the bankroll, the smallest is never more than
+    /// the bankroll so going all in is always possible
+    pub(crate) fn wager_range(&self, bankroll: ScoreAmount) -> (ScoreAmount, ScoreAmount) {
+        let max_wager = bankroll.max(0);
+        (self.minimum_wager.clamp(0, max_wager), max_wager)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Team {
+    /// The players on the team, in the order they joined
+    pub members: Vec<Player>,
+    /// The member who speaks for the team under the captain policy
+    pub captain: Option<Player>,
+}
+
 #[derive(Clone, Default, Deserialize, Serialize)]
 pub(crate) struct Game {
     /// The list of players in the game
     pub players: HashSet<Player>,
+    /// The list of players who joined mid-round and will play from the next round
+    #[serde(default)]
+    pub pending_players: HashSet<Player>,
+    /// The list of read-only viewers of the game, they never take part in a round
+    #[serde(default)]
+    pub spectators: HashSet<Spectator>,
+    /// The connection status of every player
+    #[serde(default)]
+    pub sessions: HashMap<Player, Session>,
+    /// The teams players have joined, players without a team play on their own
+    #[serde(default)]
+    pub teams: HashMap<TeamName, Team>,
+    /// The rules the game is played with
+    #[serde(default)]
+    pub settings: GameSettings,
+    /// The persistent profiles players have linked their seat to, kept after they leave
+    #[serde(default)]
+    pub profiles: HashMap<Player, ProfileId>,
+    /// The players the server plays for, with how well they play
+    #[serde(default)]
+    pub bots: HashMap<Player, Difficulty>,
+    /// If the game is over, no more actions can be taken in a finished game
+    #[serde(default)]
+    pub finished: bool,
+    /// Everything that has happened in the game, the rest of the game is built from these
+    #[serde(skip)]
+    pub events: Vec<RecordedEvent>,
     /// The list of rounds in the game with the most recent round being the last item in the list
     pub rounds: Vec<Round>,
     /// The location to get questions from
@@ -227,79 +747,674 @@ pub(crate) struct Game {
 }
 
 impl Game {
-    pub(crate) fn add_player(&mut self, player: Player) -> Result<()> {
-        // Only allow adding players at the start of a round
-        if self.current_round_state() != RoundState::Start {
-            return Err(Error::RoundNotInStartState);
+    pub(crate) fn new(question_location: GetQuestionLocation, settings: GameSettings) -> Self {
+        let mut game = Game::default();
+        game.record(GameEvent::Created {
+            question_location,
+            settings,
+        });
+        game
+    }
+
+    /// Rebuild a game by applying its events in order
+    pub(crate) fn replay(events: impl IntoIterator<Item = RecordedEvent>) -> Self {
+        let mut game = Game::default();
+        for recorded in events {
+            game.apply(&recorded.event);
+            game.events.push(recorded);
         }
-        if self.players.insert(player) {
-            Ok(())
+        game
+    }
+
+    /// Rebuild a game from events someone else sent, checking each one the way the live game
+    /// would have before applying it
+    pub(crate) fn replay_checked(events: Vec<RecordedEvent>) -> Result<Self> {
+        let mut game = Game::default();
+        let count = events.len();
+        for (index, recorded) in events.into_iter().enumerate() {
+            let recorded_before = game.events.len();
+            game.check_event(recorded.event)
+                .map_err(|e| Error::InvalidGameLog {
+                    index,
+                    reason: e.to_string(),
+                })?;
+            // Keep the original times rather than when the import happened
+            for new in &mut game.events[recorded_before..] {
+                new.at = recorded.at;
+            }
+        }
+        if game.rounds.is_empty() {
+            return Err(Error::InvalidGameLog {
+                index: count,
+                reason: Error::UnexpectedEvent.to_string(),
+            });
+        }
+        Ok(game)
+    }
+
+    /// Apply an event through the same checks as the request that would have caused it
+    fn check_event(&mut self, event: GameEvent) -> Result<()> {
+        // Everything after the first event needs a round to happen in, and nothing happens in a
+        // finished game
+        let expected = match &event {
+            GameEvent::Created { .. } => self.events.is_empty(),
+            GameEvent::RoundStarted { .. } => !self.events.is_empty(),
+            _ => !self.rounds.is_empty(),
+        };
+        if !expected || self.finished {
+            return Err(Error::UnexpectedEvent);
+        }
+        match event {
+            GameEvent::Created {
+                question_location,
+                settings,
+            } => {
+                settings.scoring.validate()?;
+                *self = Game::new(question_location, settings);
+            }
+            GameEvent::RoundStarted { question } => {
+                if !self.rounds.is_empty() && self.current_round_state() != RoundState::Complete {
+                    return Err(Error::UnexpectedEvent);
+                }
+                self.add_round_if_complete(question);
+            }
+            GameEvent::PlayerJoined { player, .. } => {
+                // Later events refer to the player by the name in the log
+                if self.add_player(player.clone())?.player != player {
+                    return Err(Error::UnexpectedEvent);
+                }
+            }
+            GameEvent::PlayerLeft { player } => {
+                if !self.sessions.contains_key(&player) {
+                    return Err(Error::PlayerNotFound);
+                }
+                self.remove_player(player)?;
+            }
+            GameEvent::PlayerReconnected { player } | GameEvent::PlayerDisconnected { player } => {
+                if !self.sessions.contains_key(&player) {
+                    return Err(Error::PlayerNotFound);
+                }
+                // Nobody is listening to an imported game yet
+            }
+            // Whoever sent the log has not shown they own the profiles, and tokens are handed
+            // out again after an import
+            GameEvent::ProfileLinked { .. } | GameEvent::TokenIssued { .. } => (),
+            GameEvent::BotAdded { player, difficulty } => {
+                if !self.sessions.contains_key(&player) {
+                    return Err(Error::PlayerNotFound);
+                }
+                if self.bots.contains_key(&player) {
+                    return Err(Error::PlayerConflict);
+                }
+                self.record(GameEvent::BotAdded { player, difficulty });
+            }
+            GameEvent::Finished => self.finish(),
+            GameEvent::SpectatorJoined { spectator } => self.add_spectator(spectator)?,
+            GameEvent::SpectatorLeft { spectator } => self.remove_spectator(&spectator),
+            GameEvent::TeamJoined { player, team } => self.join_team(player, team)?,
+            GameEvent::TeamLeft { player } => self.exit_team(&player)?,
+            GameEvent::Guessed { guess } => self.guess(guess)?,
+            GameEvent::Wagered { wager } => self.wager(wager)?,
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, event: GameEvent) {
+        self.apply(&event);
+        self.events.push(RecordedEvent::now(event));
+    }
+
+    /// Change the game to reflect something that happened. Events are only recorded once they
+    /// have been validated so applying one always succeeds.
+    fn apply(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::Created {
+                question_location,
+                settings,
+            } => {
+                self.question_location = *question_location;
+                self.settings = settings.clone();
+            }
+            GameEvent::RoundStarted { question } => {
+                self.rounds.push(Round::new(question.clone()));
+                // Anyone who joined mid-round gets to play from this round on
+                let pending_players = std::mem::take(&mut self.pending_players);
+                self.players.extend(pending_players);
+            }
+            GameEvent::PlayerJoined { player, token } => {
+                // The player is connected once their client starts listening for updates
+                let session = Session {
+                    token: token.clone(),
+                    connected: false,
+                };
+                self.sessions.insert(player.clone(), session);
+                let state = self.current_round_state();
+                if self.players.is_empty() && state != RoundState::Complete {
+                    // Nobody is left to finish the round, so start it over with the new player
+                    let round = self.current_round_mut();
+                    *round = Round::new(round.question.clone());
+                    self.players.insert(player.clone());
+                } else if state == RoundState::Start {
+                    self.players.insert(player.clone());
+                } else {
+                    // Players joining mid-round sit out until the next round starts
+                    self.pending_players.insert(player.clone());
+                }
+            }
+            GameEvent::PlayerLeft { player } => self.apply_player_left(player),
+            GameEvent::PlayerReconnected { player } => self.set_connected(player, true),
+            GameEvent::PlayerDisconnected { player } => self.set_connected(player, false),
+            GameEvent::ProfileLinked { player, profile } => {
+                self.profiles.insert(player.clone(), profile.clone());
+            }
+            GameEvent::BotAdded { player, difficulty } => {
+                self.bots.insert(player.clone(), *difficulty);
+                self.set_connected(player, true);
+            }
+            GameEvent::Finished => self.finished = true,
+            GameEvent::TokenIssued { player, token } => {
+                if let Some(session) = self.sessions.get_mut(player) {
+                    session.token = token.clone();
+                }
+            }
+            GameEvent::SpectatorJoined { spectator } => {
+                self.spectators.insert(spectator.clone());
+            }
+            GameEvent::SpectatorLeft { spectator } => {
+                self.spectators.remove(spectator);
+            }
+            GameEvent::TeamJoined { player, team } => {
+                self.leave_team(player);
+                let team = self.teams.entry(team.clone()).or_default();
+                team.captain.get_or_insert_with(|| player.clone());
+                team.members.push(player.clone());
+            }
+            GameEvent::TeamLeft { player } => {
+                self.leave_team(player);
+            }
+            GameEvent::Guessed { guess } => {
+                self.current_round_mut().add_or_replace_guess(guess.clone());
+                self.advance_current_round();
+            }
+            GameEvent::Wagered { wager } => {
+                self.current_round_mut()
+                    .wagers
+                    .add_or_replace(wager.clone());
+                self.advance_current_round();
+            }
+        }
+    }
+
+    /// Seat a new player, the name they are given back is the checked and normalised form of
+    /// the one asked for
+    pub(crate) fn add_player(&mut self, player: Player) -> Result<SessionData> {
+        let player = validation::player_name(&player)?;
+        let key = validation::name_key(&player);
+        if self
+            .players
+            .iter()
+            .chain(&self.pending_players)
+            .chain(self.teams.keys())
+            .any(|name| validation::name_key(name) == key)
+        {
+            return Err(Error::PlayerConflict);
+        }
+        let token = generate_token();
+        self.record(GameEvent::PlayerJoined {
+            player: player.clone(),
+            token: token.clone(),
+        });
+        Ok(SessionData { player, token })
+    }
+
+    /// Check a player whose client lost track of the game still holds their seat, they show as
+    /// connected again once the client listens for updates
+    pub(crate) fn reconnect(&self, session_data: &SessionData) -> Result<()> {
+        self.check_session(session_data)
+    }
+
+    /// Record the player's client starting or stopping listening for updates, returns false if
+    /// nothing changed
+    fn update_connection(&mut self, player: &Player, connected: bool) -> bool {
+        let changed = self
+            .sessions
+            .get(player)
+            .is_some_and(|session| session.connected != connected);
+        if !changed || self.finished {
+            return false;
+        }
+        let player = player.clone();
+        self.record(if connected {
+            GameEvent::PlayerReconnected { player }
         } else {
-            Err(Error::PlayerConflict)
+            GameEvent::PlayerDisconnected { player }
+        });
+        true
+    }
+
+    /// Count the player's results towards a persistent profile, the profile must already be
+    /// verified
+    pub(crate) fn link_profile(&mut self, player: Player, profile: ProfileId) -> Result<()> {
+        if !self.sessions.contains_key(&player) {
+            return Err(Error::PlayerNotFound);
+        }
+        // One person playing several seats would have every seat counted in their stats
+        if self
+            .profiles
+            .iter()
+            .any(|(seat, linked)| *linked == profile && *seat != player)
+        {
+            return Err(Error::ProfileConflict);
+        }
+        self.record(GameEvent::ProfileLinked { player, profile });
+        Ok(())
+    }
+
+    /// Seat a player the server plays for
+    pub(crate) fn add_bot(&mut self, player: Player, difficulty: Difficulty) -> Result<()> {
+        let player = self.add_player(player)?.player;
+        self.record(GameEvent::BotAdded { player, difficulty });
+        Ok(())
+    }
+
+    /// Start the next round with the question if the current one is complete, then let the bots
+    /// take their turns until they are waiting on someone else
+    pub(crate) fn play_bots(&mut self, mut question: Option<Question>) {
+        loop {
+            if self.current_round_state() == RoundState::Complete {
+                match question.take() {
+                    Some(question) => self.add_round_if_complete(question),
+                    None => return,
+                }
+            }
+            if !self.play_bots_turn() {
+                return;
+            }
+        }
+    }
+
+    /// The nearest guess to the one given that the duplicate policy lets the player make
+    fn allowed_guess(&self, player: &Player, guess: AnswerAmount) -> AnswerAmount {
+        if self.settings.duplicate_guesses == DuplicateGuessPolicy::Merge {
+            return guess;
+        }
+        // On a full board the guess shares a slot, see `guess`
+        self.free_guess(self.participant(player), guess)
+            .unwrap_or(guess)
+    }
+
+    /// The nearest guess to the one given that no other participant has made, none if the
+    /// question allows fewer guesses than there are on the board
+    fn free_guess(&self, participant: &str, guess: AnswerAmount) -> Option<AnswerAmount> {
+        let round = self.current_round();
+        let (min, max) = round.question.guess_range();
+        // Each guess on the board can block at most one step in either direction
+        (0..=round.guesses.len() as AnswerAmount)
+            .flat_map(|step| [guess.checked_sub(step), guess.checked_add(step)])
+            .flatten()
+            .find(|&candidate| {
+                (min..=max).contains(&candidate)
+                    && !round.guesses.taken_by_other(candidate, participant)
+            })
+    }
+
+    /// Have every bot still owing a guess or wager submit one, returns false if none did
+    fn play_bots_turn(&mut self) -> bool {
+        let mut rng = rand::thread_rng();
+        let mut bots: Vec<(Player, Difficulty)> = self
+            .bots
+            .iter()
+            .filter(|(bot, _)| self.players.contains(*bot))
+            .map(|(bot, difficulty)| (bot.clone(), *difficulty))
+            .collect();
+        bots.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut played = false;
+        for (bot, difficulty) in bots {
+            let round = self.current_round();
+            match round.phase {
+                RoundState::Start | RoundState::CollectingGuesses => {
+                    // Bots wait for a person to go first so late joiners can still get in
+                    let people_waiting = round.guesses.is_empty()
+                        && self.players.iter().any(|p| !self.bots.contains_key(p));
+                    if people_waiting || round.guesses.iter().any(|g| g.player == bot) {
+                        continue;
+                    }
+                    let view = RoundView::new(self, &bot);
+                    let guess = difficulty.guess(&view, round.question.answer, &mut rng);
+                    let guess = self.allowed_guess(&bot, round.question.clamp_guess(guess));
+                    played |= self
+                        .guess(Guess {
+                            player: bot,
+                            guess,
+                            team: None,
+                        })
+                        .is_ok();
+                }
+                RoundState::CollectingWagers => {
+                    if round.wagers.iter().any(|w| w.player == bot) {
+                        continue;
+                    }
+                    let view = RoundView::new(self, &bot);
+                    let (guess, wager) = difficulty.wager(&view, &mut rng);
+                    let (min_wager, max_wager) = self.settings.scoring.wager_range(view.bankroll);
+                    let wager = wager.clamp(min_wager, max_wager);
+                    played |= self
+                        .wager(Wager {
+                            player: bot,
+                            guess,
+                            wager,
+                            team: None,
+                            all_in: false,
+                        })
+                        .is_ok();
+                }
+                RoundState::Complete => break,
+            }
+        }
+        played
+    }
+
+    pub(crate) fn finish(&mut self) {
+        self.record(GameEvent::Finished);
+    }
+
+    /// Hand every player a new token, used when the old ones were not kept like after an import
+    pub(crate) fn reissue_tokens(&mut self) -> Vec<SessionData> {
+        let mut players: Vec<Player> = self.sessions.keys().cloned().collect();
+        players.sort();
+        players
+            .into_iter()
+            .map(|player| {
+                let token = generate_token();
+                self.record(GameEvent::TokenIssued {
+                    player: player.clone(),
+                    token: token.clone(),
+                });
+                SessionData { player, token }
+            })
+            .collect()
+    }
+
+    pub(crate) fn check_session(&self, session_data: &SessionData) -> Result<()> {
+        let session = self
+            .sessions
+            .get(&session_data.player)
+            .ok_or(Error::PlayerNotFound)?;
+        // Sessions rebuilt from exported events have no token until one is reissued
+        if session.token.is_empty() || session.token != session_data.token {
+            return Err(Error::InvalidToken);
+        }
+        Ok(())
+    }
+
+    fn set_connected(&mut self, player: &Player, connected: bool) {
+        if let Some(session) = self.sessions.get_mut(player) {
+            session.connected = connected;
         }
     }
 
     pub(crate) fn remove_player(&mut self, player: Player) -> Result<()> {
-        // Only allow removing players at the start of a round
-        if self.current_round_state() != RoundState::Start {
-            return Err(Error::RoundNotInStartState);
+        if self.players.contains(&player) || self.pending_players.contains(&player) {
+            self.record(GameEvent::PlayerLeft { player });
         }
-        self.players.remove(&player);
         Ok(())
     }
 
-    pub(crate) fn guess(&mut self, guess: Guess) -> Result<()> {
-        let player = &guess.player;
-        // Confirm the player exists
-        if !self.players.contains(player) {
+    fn apply_player_left(&mut self, player: &Player) {
+        self.sessions.remove(player);
+        self.bots.remove(player);
+        let participant = self.participant(player).to_string();
+        let team_disbanded = self.leave_team(player);
+        if self.pending_players.remove(player) {
+            return;
+        }
+        if !self.players.remove(player) {
+            return;
+        }
+        // A team keeps its guess and wager as long as someone is left on it
+        if &participant == player || team_disbanded {
+            let state = self.current_round_state();
+            let round = self.current_round_mut();
+            // Drop whatever the player still had pending for the round. Once wagers are being
+            // collected their guess stays on the board since others may have wagered on it.
+            match state {
+                RoundState::Start | RoundState::CollectingGuesses => {
+                    round.remove_guess(&participant)
+                }
+                RoundState::CollectingWagers => round.wagers.remove(&participant),
+                RoundState::Complete => (),
+            }
+        }
+        // If nobody is left in the round, restart it for whoever is waiting
+        if self.players.is_empty() && !self.pending_players.is_empty() {
+            self.players = std::mem::take(&mut self.pending_players);
+            let round = self.current_round_mut();
+            *round = Round::new(round.question.clone());
+        }
+        self.advance_current_round();
+    }
+
+    pub(crate) fn join_team(&mut self, player: Player, team: TeamName) -> Result<()> {
+        if !self.players.contains(&player) && !self.pending_players.contains(&player) {
             return Err(Error::PlayerNotFound);
         }
+        let team = validation::player_name(&team)?;
+        let key = validation::name_key(&team);
+        if self
+            .players
+            .iter()
+            .chain(&self.pending_players)
+            .any(|name| validation::name_key(name) == key)
+        {
+            return Err(Error::TeamConflict);
+        }
+        // A name that only differs by case joins the team that already has it
+        let team = self
+            .teams
+            .keys()
+            .find(|name| validation::name_key(name) == key)
+            .cloned()
+            .unwrap_or(team);
+        // Switching sides mid-round would move a guess or wager between participants
+        let phase = self.current_round_state();
+        if phase != RoundState::Start {
+            return Err(Error::RoundNotInStartState { phase });
+        }
+        self.record(GameEvent::TeamJoined { player, team });
+        Ok(())
+    }
+
+    pub(crate) fn exit_team(&mut self, player: &Player) -> Result<()> {
+        let phase = self.current_round_state();
+        if phase != RoundState::Start {
+            return Err(Error::RoundNotInStartState { phase });
+        }
+        self.record(GameEvent::TeamLeft {
+            player: player.clone(),
+        });
+        Ok(())
+    }
+
+    /// Take the player off their team, returns true if that left the team empty
+    fn leave_team(&mut self, player: &Player) -> bool {
+        let Some(name) = self.team_of(player).cloned() else {
+            return false;
+        };
+        let team = self.teams.get_mut(&name).expect("team to exist");
+        team.members.retain(|member| member != player);
+        // The captaincy passes to the member who has been on the team the longest
+        if team.captain.as_ref() == Some(player) {
+            team.captain = team.members.first().cloned();
+        }
+        if team.members.is_empty() {
+            self.teams.remove(&name);
+            return true;
+        }
+        false
+    }
+
+    fn team_of(&self, player: &Player) -> Option<&TeamName> {
+        self.teams
+            .iter()
+            .find(|(_, team)| team.members.contains(player))
+            .map(|(name, _)| name)
+    }
+
+    /// The team the player plays for, or the player themselves if they are not on a team
+    pub(crate) fn participant<'a>(&'a self, player: &'a Player) -> &'a str {
+        self.team_of(player).unwrap_or(player)
+    }
+
+    fn participants(&self) -> HashSet<String> {
+        self.players
+            .iter()
+            .map(|p| self.participant(p).to_string())
+            .collect()
+    }
+
+    /// Confirm the player is allowed to submit on behalf of their team and return the team
+    fn check_team_submission(&self, player: &Player) -> Result<Option<TeamName>> {
+        let Some(name) = self.team_of(player) else {
+            return Ok(None);
+        };
+        if self.settings.team_guess_policy == TeamGuessPolicy::Captain
+            && self.teams[name].captain.as_ref() != Some(player)
+        {
+            return Err(Error::NotTeamCaptain);
+        }
+        Ok(Some(name.clone()))
+    }
+
+    pub(crate) fn add_spectator(&mut self, spectator: Spectator) -> Result<()> {
+        let spectator = validation::player_name(&spectator)?;
+        if self.spectator_named(&spectator).is_some() {
+            return Err(Error::SpectatorConflict);
+        }
+        self.record(GameEvent::SpectatorJoined { spectator });
+        Ok(())
+    }
+
+    pub(crate) fn remove_spectator(&mut self, spectator: &Spectator) {
+        let Ok(spectator) = validation::player_name(spectator) else {
+            return;
+        };
+        if let Some(spectator) = self.spectator_named(&spectator).cloned() {
+            self.record(GameEvent::SpectatorLeft { spectator });
+        }
+    }
+
+    fn spectator_named(&self, name: &str) -> Option<&Spectator> {
+        let key = validation::name_key(name);
+        self.spectators
+            .iter()
+            .find(|spectator| validation::name_key(spectator) == key)
+    }
+
+    /// A view of the game suitable for showing on a shared screen, it never reveals the answer
+    /// to the round in progress
+    pub(crate) fn spectator_view(&self) -> SpectatorView {
+        let round = self.current_round();
+        let mut board = round.board.clone();
+        board.reverse();
+        let previous_round = self
+            .rounds
+            .len()
+            .checked_sub(2)
+            .map(|index| self.rounds[index].clone());
+        SpectatorView {
+            question: round.question.question.clone(),
+            phase: round.phase,
+            board,
+            wagers: round.wagers.clone(),
+            scores: self.get_score(),
+            players: self.players.clone(),
+            pending_players: self.pending_players.clone(),
+            previous_round,
+        }
+    }
+
+    fn check_player_in_round(&self, player: &Player) -> Result<()> {
+        if self.players.contains(player) {
+            Ok(())
+        } else if self.pending_players.contains(player) {
+            Err(Error::PlayerWaitingForNextRound)
+        } else {
+            Err(Error::PlayerNotFound)
+        }
+    }
+
+    pub(crate) fn guess(&mut self, mut guess: Guess) -> Result<()> {
+        // Confirm the player exists and is playing this round
+        self.check_player_in_round(&guess.player)?;
+        guess.team = self.check_team_submission(&guess.player)?;
         // Confirm we are collecting guesses for the current round
         match self.current_round_state() {
             RoundState::Start | RoundState::CollectingGuesses => (),
-            _ => return Err(Error::RoundNotInCollectingGuessesState),
+            phase => return Err(Error::RoundNotInCollectingGuessesState { phase }),
+        }
+        // Confirm the guess is one the question allows
+        let round = self.current_round();
+        let (min, max) = round.question.guess_range();
+        if !(min..=max).contains(&guess.guess) {
+            return Err(Error::GuessOutOfRange { min, max });
+        }
+        // Once every guess the question allows is taken, the rest have to share a slot or the
+        // round could never finish
+        if self.settings.duplicate_guesses == DuplicateGuessPolicy::Reject
+            && round
+                .guesses
+                .taken_by_other(guess.guess, guess.participant())
+            && self.free_guess(guess.participant(), guess.guess).is_some()
+        {
+            return Err(Error::GuessTaken { guess: guess.guess });
         }
         // Add or replace the answer
-        let round = self.current_round_mut();
-        round.guesses.add_or_replace(guess);
+        self.record(GameEvent::Guessed { guess });
         Ok(())
     }
 
-    pub(crate) fn wager(&mut self, wager: Wager) -> Result<()> {
-        let player = &wager.player;
-        // Confirm the player exists
-        if !self.players.contains(player) {
-            return Err(Error::PlayerNotFound);
-        }
+    pub(crate) fn wager(&mut self, mut wager: Wager) -> Result<()> {
+        // Confirm the player exists and is playing this round
+        self.check_player_in_round(&wager.player)?;
+        wager.team = self.check_team_submission(&wager.player)?;
         // Confirm we are collecting wagers for the current round
-        if self.current_round_state() != RoundState::CollectingWagers {
-            return Err(Error::RoundNotInCollectingWagersState);
+        let phase = self.current_round_state();
+        if phase != RoundState::CollectingWagers {
+            return Err(Error::RoundNotInCollectingWagersState { phase });
         }
         // Confirm the wagers are valid
         let scores = self.get_score();
-        let round = self.current_round_mut();
+        let round = self.current_round();
         if let Some(some_wager_guess) = wager.guess {
             if !round.guesses.contains(some_wager_guess) {
                 return Err(Error::GuessNotFound);
             }
         }
-        // Check that the amount is less than or equal to their score so far
-        match scores.get(&wager.player) {
-            Some(score) => {
-                if &wager.wager > score {
-                    return Err(Error::InvalidWager);
-                }
-            }
-            None => return Err(Error::PlayerNotFound),
+        // Check the amount fits the rules and their score so far, teams share a bankroll
+        let Some(&bankroll) = scores.get(wager.participant()) else {
+            return Err(Error::PlayerNotFound);
+        };
+        let (min_wager, max_wager) = self.settings.scoring.wager_range(bankroll);
+        if wager.all_in {
+            wager.wager = max_wager;
+        }
+        if wager.wager < 0 {
+            return Err(Error::NegativeWager);
+        }
+        if wager.wager > max_wager {
+            return Err(Error::InvalidWager { max_wager });
+        }
+        if wager.wager < min_wager {
+            return Err(Error::WagerBelowMinimum { min_wager });
         }
         // Add or replace the guess
-        round.wagers.add_or_replace(wager);
+        self.record(GameEvent::Wagered { wager });
         Ok(())
     }
 
     pub(crate) fn add_round_if_complete(&mut self, question: Question) {
         if self.rounds.is_empty() || self.current_round_state() == RoundState::Complete {
-            self.rounds.push(Round::new(question));
+            self.record(GameEvent::RoundStarted { question });
         }
     }
 
@@ -314,16 +1429,21 @@ impl Game {
     }
 
     fn current_round_state(&self) -> RoundState {
-        let players = self.players.len();
-        let round = self.current_round();
-        round.state(players)
+        self.current_round().phase
+    }
+
+    fn advance_current_round(&mut self) {
+        let index = self.rounds.len() - 1;
+        let participants = self.participants();
+        self.rounds[index].advance(&participants);
     }
 
     pub fn get_score(&self) -> Scores {
         let mut scores = HashMap::new();
-        // Everyone start off with a score of 1
-        for player in &self.players {
-            scores.insert(player.clone(), 1);
+        // Everyone starts off with the same score, teams are scored as one
+        let rules = self.settings.scoring;
+        for player in self.players.iter().chain(&self.pending_players) {
+            scores.insert(self.participant(player).to_string(), rules.starting_score);
         }
         // Do not score the current round if it is not Complete
         let last_round_to_score = if self.current_round_state() == RoundState::Complete {
@@ -332,49 +1452,387 @@ impl Game {
             self.rounds.len() - 1
         };
         for round in &self.rounds[..last_round_to_score] {
-            let round_score_changes = round.get_score_changes(3, 3);
+            let round_score_changes = rules.score_changes(round);
             for (player, round_score_change) in &round_score_changes {
-                let score = scores.entry(player.clone()).or_insert(1);
-                *score += round_score_change;
+                // Scores can grow quickly when players keep going all in, so stop at the limit
+                let score = scores.entry(player.clone()).or_insert(rules.starting_score);
+                *score = score.saturating_add(*round_score_change);
+            }
+            // Top up anyone who went broke so they still have something to wager
+            if rules.reseed_score > 0 {
+                for score in scores.values_mut() {
+                    if *score <= 0 {
+                        *score = rules.reseed_score;
+                    }
+                }
             }
         }
         scores
     }
 }
 
+/// Something that happened in a game
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "event")]
+pub(crate) enum GameEvent {
+    Created {
+        question_location: GetQuestionLocation,
+        settings: GameSettings,
+    },
+    RoundStarted {
+        question: Question,
+    },
+    PlayerJoined {
+        player: Player,
+        /// Kept out of exported events so they can be shared without giving away seats
+        #[serde(skip_serializing, default)]
+        token: Token,
+    },
+    PlayerLeft {
+        player: Player,
+    },
+    PlayerReconnected {
+        player: Player,
+    },
+    PlayerDisconnected {
+        player: Player,
+    },
+    ProfileLinked {
+        player: Player,
+        profile: ProfileId,
+    },
+    BotAdded {
+        player: Player,
+        difficulty: Difficulty,
+    },
+    Finished,
+    TokenIssued {
+        player: Player,
+        #[serde(skip_serializing, default)]
+        token: Token,
+    },
+    SpectatorJoined {
+        spectator: Spectator,
+    },
+    SpectatorLeft {
+        spectator: Spectator,
+    },
+    TeamJoined {
+        player: Player,
+        team: TeamName,
+    },
+    TeamLeft {
+        player: Player,
+    },
+    Guessed {
+        guess: Guess,
+    },
+    Wagered {
+        wager: Wager,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct RecordedEvent {
+    /// When the event happened, in seconds since the Unix epoch
+    pub at: u64,
+    #[serde(flatten)]
+    pub event: GameEvent,
+}
+
+impl RecordedEvent {
+    fn now(event: GameEvent) -> Self {
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+        Self { at, event }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct SpectatorView {
+    /// The question for the current round, without its answer
+    pub question: String,
+    /// The phase the current round is in
+    pub phase: RoundState,
+    /// The distinct guesses for the current round sorted from highest to lowest
+    pub board: Vec<BoardSlot>,
+    /// The wagers made so far in the current round
+    pub wagers: Wagers,
+    /// The score of every player
+    pub scores: Scores,
+    /// The list of players in the game
+    pub players: HashSet<Player>,
+    /// The list of players who will play from the next round
+    pub pending_players: HashSet<Player>,
+    /// The last finished round, including its answer
+    pub previous_round: Option<Round>,
+}
+
+/// Characters used in join codes, leaving out ones that are easily confused like 0/O and 1/I/L
+const JOIN_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+const JOIN_CODE_LENGTH: usize = 6;
+
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub(crate) enum GameUpdateKind {
+    /// update
+    Updated,
+    /// expired
+    Expired,
+    /// deleted
+    Deleted,
+}
+
+/// The state of a game sent to everyone listening to it
+#[derive(Clone)]
+pub(crate) struct GameUpdate {
+    pub kind: GameUpdateKind,
+    pub game: Game,
+}
+
+/// The number of updates a slow listener can fall behind before it starts missing them
+const GAME_UPDATE_CAPACITY: usize = 16;
+
+struct GameEntry {
+    game: Game,
+    /// When a player last did something in the game
+    last_activity: Instant,
+    updates: broadcast::Sender<GameUpdate>,
+    /// How many update streams each player has open, a player with none is disconnected
+    listeners: HashMap<Player, usize>,
+}
+
+impl GameEntry {
+    fn new(game: Game) -> Self {
+        let (updates, _) = broadcast::channel(GAME_UPDATE_CAPACITY);
+        Self {
+            game,
+            last_activity: Instant::now(),
+            updates,
+            listeners: HashMap::new(),
+        }
+    }
+
+    fn send(&self, kind: GameUpdateKind) {
+        // Nobody listening is not an error
+        let _ = self.updates.send(GameUpdate {
+            kind,
+            game: self.game.clone(),
+        });
+    }
+}
+
+/// A player's open update stream, the player shows as connected until their last one is dropped
+pub(crate) struct Listener {
+    /// Weak so a stream left open does not keep a deleted game alive
+    entry: Weak<Mutex<GameEntry>>,
+    player: Player,
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        // Nothing to record once the game is gone
+        let Some(entry) = self.entry.upgrade() else {
+            return;
+        };
+        let mut entry = entry.lock();
+        let Some(listeners) = entry.listeners.get_mut(&self.player) else {
+            return;
+        };
+        *listeners -= 1;
+        if *listeners == 0 {
+            entry.listeners.remove(&self.player);
+            if entry.game.update_connection(&self.player, false) {
+                entry.send(GameUpdateKind::Updated);
+            }
+        }
+    }
+}
+
+/// All the games on the server. Each game has its own lock so a slow request in one game never
+/// holds up another, the map itself is only locked long enough to find or insert a game.
 #[derive(Default)]
-pub(crate) struct Games(HashMap<GameId, Game>);
+pub(crate) struct Games(RwLock<HashMap<GameId, Arc<Mutex<GameEntry>>>>);
 
 impl Games {
-    #[allow(clippy::map_entry)]
     pub(crate) fn create(
-        &mut self,
+        &self,
         game_id: String,
         initial_player: Player,
         question: Question,
         get_questions_from: GetQuestionLocation,
-    ) -> Result<()> {
-        if self.0.contains_key(&game_id) {
-            Err(Error::GameConflict)
-        } else {
-            let mut game = Game {
-                question_location: get_questions_from,
-                ..Default::default()
-            };
-            game.add_round_if_complete(question);
-            game.add_player(initial_player)?;
-            self.0.insert(game_id, game);
-            Ok(())
+        settings: GameSettings,
+    ) -> Result<SessionData> {
+        validation::game_id(&game_id)?;
+        settings.scoring.validate()?;
+        let mut game = Game::new(get_questions_from, settings);
+        game.add_round_if_complete(question);
+        let session_data = game.add_player(initial_player)?;
+        self.insert(game_id, game)?;
+        Ok(session_data)
+    }
+
+    /// Add an already played game, such as one rebuilt from an export
+    pub(crate) fn insert(&self, game_id: GameId, game: Game) -> Result<()> {
+        validation::game_id(&game_id)?;
+        match self.0.write().entry(game_id) {
+            Entry::Occupied(_) => Err(Error::GameConflict),
+            Entry::Vacant(entry) => {
+                entry.insert(Arc::new(Mutex::new(GameEntry::new(game))));
+                Ok(())
+            }
         }
     }
 
-    pub(crate) fn get(&mut self, game_id: &str) -> Result<&mut Game> {
-        self.0.get_mut(game_id).ok_or(Error::GameNotFound)
+    /// Create a game under a freshly generated join code
+    pub(crate) fn create_with_code(
+        &self,
+        initial_player: Player,
+        question: Question,
+        get_questions_from: GetQuestionLocation,
+        settings: GameSettings,
+    ) -> Result<NewGameData> {
+        loop {
+            let game_id = generate_code();
+            let result = self.create(
+                game_id.clone(),
+                initial_player.clone(),
+                question.clone(),
+                get_questions_from,
+                settings.clone(),
+            );
+            match result {
+                // Another game already has the code, try again
+                Err(Error::GameConflict) => continue,
+                result => return result.map(|session| NewGameData { game_id, session }),
+            }
+        }
     }
 
-    pub(crate) fn delete(&mut self, game_id: &str) {
-        self.0.remove(game_id);
+    /// The public games that can still be joined, sorted by id, with player ratings looked up by
+    /// profile
+    pub(crate) fn lobbies(&self, rating: impl Fn(&ProfileId) -> Option<Rating>) -> Vec<LobbyData> {
+        let mut lobbies: Vec<LobbyData> = self
+            .0
+            .read()
+            .iter()
+            .filter_map(|(game_id, entry)| {
+                let game = &entry.lock().game;
+                // A finished game is kept around for its results but can no longer be joined
+                let open = game.settings.visibility == Visibility::Public && !game.finished;
+                open.then(|| LobbyData {
+                    game_id: game_id.clone(),
+                    players: game.players.len() + game.pending_players.len(),
+                    rounds: game.rounds.len(),
+                    phase: game.current_round().phase,
+                    ratings: game
+                        .players
+                        .iter()
+                        .chain(&game.pending_players)
+                        .filter_map(|player| {
+                            let profile = game.profiles.get(player)?;
+                            Some((player.clone(), rating(profile)?))
+                        })
+                        .collect(),
+                })
+            })
+            .collect();
+        lobbies.sort_by(|a, b| a.game_id.cmp(&b.game_id));
+        lobbies
     }
+
+    fn entry(&self, game_id: &str) -> Result<Arc<Mutex<GameEntry>>> {
+        let games = self.0.read();
+        games
+            .get(game_id)
+            // Generated join codes are upper case but are often typed in lower case
+            .or_else(|| games.get(&game_id.to_ascii_uppercase()))
+            .cloned()
+            .ok_or(Error::GameNotFound)
+    }
+
+    /// Look at a game without counting it as activity
+    pub(crate) fn read<T>(&self, game_id: &str, view: impl FnOnce(&Game) -> T) -> Result<T> {
+        let entry = self.entry(game_id)?;
+        let entry = entry.lock();
+        Ok(view(&entry.game))
+    }
+
+    /// Apply a player's action to a game, on success everyone listening is sent the new state
+    pub(crate) fn update<T>(
+        &self,
+        game_id: &str,
+        action: impl FnOnce(&mut Game) -> Result<T>,
+    ) -> Result<T> {
+        let entry = self.entry(game_id)?;
+        let mut entry = entry.lock();
+        if entry.game.finished {
+            return Err(Error::GameFinished);
+        }
+        entry.last_activity = Instant::now();
+        let result = action(&mut entry.game)?;
+        entry.send(GameUpdateKind::Updated);
+        Ok(result)
+    }
+
+    /// Count an update stream the player opened towards them being connected
+    pub(crate) fn listen(&self, game_id: &str, session_data: &SessionData) -> Result<Listener> {
+        let entry = self.entry(game_id)?;
+        let mut locked = entry.lock();
+        locked.game.check_session(session_data)?;
+        let player = session_data.player.clone();
+        *locked.listeners.entry(player.clone()).or_default() += 1;
+        if locked.game.update_connection(&player, true) {
+            locked.send(GameUpdateKind::Updated);
+        }
+        drop(locked);
+        Ok(Listener {
+            entry: Arc::downgrade(&entry),
+            player,
+        })
+    }
+
+    pub(crate) fn subscribe(&self, game_id: &str) -> Result<broadcast::Receiver<GameUpdate>> {
+        let entry = self.entry(game_id)?;
+        let receiver = entry.lock().updates.subscribe();
+        Ok(receiver)
+    }
+
+    pub(crate) fn delete(&self, game_id: &str) {
+        let mut games = self.0.write();
+        let entry = games
+            .remove(game_id)
+            .or_else(|| games.remove(&game_id.to_ascii_uppercase()));
+        if let Some(entry) = entry {
+            entry.lock().send(GameUpdateKind::Deleted);
+        }
+    }
+
+    /// Delete every game nobody has done anything in for longer than `ttl`, returning their ids
+    pub(crate) fn reap(&self, now: Instant, ttl: Duration) -> Vec<GameId> {
+        let mut games = self.0.write();
+        let expired: Vec<GameId> = games
+            .iter()
+            .filter(|(_, entry)| now.saturating_duration_since(entry.lock().last_activity) > ttl)
+            .map(|(game_id, _)| game_id.clone())
+            .collect();
+        for game_id in &expired {
+            if let Some(entry) = games.remove(game_id) {
+                entry.lock().send(GameUpdateKind::Expired);
+            }
+        }
+        expired
+    }
+}
+
+fn generate_code() -> GameId {
+    let mut rng = rand::thread_rng();
+    (0..JOIN_CODE_LENGTH)
+        .map(|_| {
+            let index = rng.gen_range(0..JOIN_CODE_ALPHABET.len());
+            char::from(JOIN_CODE_ALPHABET[index])
+        })
+        .collect()
 }
 
 #[derive(Default, Clone, Copy, Deserialize, Serialize, Debug)]
@@ -0,0 +1,85 @@
+use unicode_normalization::UnicodeNormalization;
+
+use crate::types::{Error, GameId, Player, Result};
+
+/// The most characters a player name can have
+pub(crate) const MAX_PLAYER_NAME_LENGTH: usize = 32;
+/// The most characters a game id can have
+pub(crate) const MAX_GAME_ID_LENGTH: usize = 32;
+
+/// Check a player name and put it in its canonical form: NFC normalised, trimmed, with runs of
+/// spaces collapsed to one
+pub(crate) fn player_name(name: &str) -> Result<Player> {
+    let name: String = name.nfc().collect();
+    if let Some(character) = name
+        .chars()
+        .find(|c| c.is_control() || is_format(*c) || (c.is_whitespace() && *c != ' '))
+    {
+        return Err(Error::PlayerNameInvalidCharacter { character });
+    }
+    let name = name.split(' ').filter(|word| !word.is_empty());
+    let name = name.collect::<Vec<_>>().join(" ");
+    if name.is_empty() {
+        return Err(Error::PlayerNameEmpty);
+    }
+    if name.chars().count() > MAX_PLAYER_NAME_LENGTH {
+        return Err(Error::PlayerNameTooLong {
+            max_length: MAX_PLAYER_NAME_LENGTH,
+        });
+    }
+    Ok(name)
+}
+
+/// If the character is in the Unicode format category (Cf). These are invisible, like zero-width
+/// spaces and bidi overrides, so would let two names look the same.
+fn is_format(c: char) -> bool {
+    matches!(
+        c,
+        '\u{AD}'
+            | '\u{600}'..='\u{605}'
+            | '\u{61C}'
+            | '\u{6DD}'
+            | '\u{70F}'
+            | '\u{890}'..='\u{891}'
+            | '\u{8E2}'
+            | '\u{180E}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{2066}'..='\u{206F}'
+            | '\u{FEFF}'
+            | '\u{FFF9}'..='\u{FFFB}'
+            | '\u{110BD}'
+            | '\u{110CD}'
+            | '\u{13430}'..='\u{1343F}'
+            | '\u{1BCA0}'..='\u{1BCA3}'
+            | '\u{1D173}'..='\u{1D17A}'
+            | '\u{E0001}'
+            | '\u{E0020}'..='\u{E007F}'
+    )
+}
+
+/// The form two names are compared in, names that only differ by case belong to the same player
+pub(crate) fn name_key(name: &str) -> String {
+    name.to_lowercase()
+}
+
+/// Check a game id chosen by a player, it ends up in URLs so only letters, digits, `-` and `_`
+/// are allowed
+pub(crate) fn game_id(game_id: &GameId) -> Result<()> {
+    if game_id.is_empty() {
+        return Err(Error::GameIdEmpty);
+    }
+    if let Some(character) = game_id
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && *c != '-' && *c != '_')
+    {
+        return Err(Error::GameIdInvalidCharacter { character });
+    }
+    if game_id.len() > MAX_GAME_ID_LENGTH {
+        return Err(Error::GameIdTooLong {
+            max_length: MAX_GAME_ID_LENGTH,
+        });
+    }
+    Ok(())
+}